@@ -4,9 +4,12 @@ use raytracer::raytracer;
 use raytracer::{read_scene_file, write_image};
 
 fn render_image(file_path_str: &String) {
-    let scene = read_scene_file(file_path_str);
+    let scene = match read_scene_file(file_path_str) {
+        Ok(s) => s,
+        Err(e) => panic!("Cannot read scene file \"{}\": {}", file_path_str, e),
+    };
     let pixels = raytracer::render(&scene);
-    let img_obj = raytracer::build_image((scene.img_width, scene.img_height), &pixels);
+    let img_obj = raytracer::build_image(&pixels, &raytracer::ToneMapping::new());
     write_image(&"out.png".to_string(), img_obj);
 }
 
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::path::Path;
+
+use raytracer::raytracer::{FrameRange, ToneMapOperator};
+
+pub const USAGE: &str = "\
+Usage: raytracer [options] <scene>...
+
+Renders each scene file to an image, written where the scene's output command says.
+Otherwise, with a single scene the image is written to out.png, and with several each
+image is named after its scene file. Animated scenes get the frame number added to
+the file name. A scene named - is read from standard input.
+
+Options:
+  -o, --output <path>         Image to write instead of the scene's output, its
+                              extension picking the format
+  -f, --format <ext>          Image format, e.g. png, jpg, bmp, exr, hdr or pfm
+  -r, --resolution <w>x<h>    Override the image size
+  -d, --maxdepth <n>          Override the maximum recursion depth
+  -s, --samples <n>           Override the samples per pixel
+  -j, --threads <n>           Worker threads, 0 for one per logical CPU (default)
+      --frames <first> <last> Render this range of frames instead of the scene's
+      --tonemap <op>          Tone mapping operator for 8-bit images: clamp,
+                              reinhard or aces
+      --exposure <x>          Multiply colors by this before tone mapping
+      --srgb                  Encode 8-bit images with the sRGB curve
+      --strict                Stop at the first problem in a scene instead of
+                              warning and carrying on
+  -q, --quiet                 Only report errors
+  -v, --verbose               Report scene statistics and timings
+  -h, --help                  Show this help";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug, Clone)]
+pub struct CliOptions {
+    pub scenes: Vec <String>,
+    pub output: Option <String>,
+    pub format: Option <String>,
+    pub resolution: Option <(usize, usize)>,
+    pub max_depth: Option <usize>,
+    pub samples: Option <usize>,
+    pub threads: usize,
+    pub frames: Option <FrameRange>,
+    // Tone mapping settings overriding the scene's
+    pub tone_map: Option <ToneMapOperator>,
+    pub exposure: Option <f64>,
+    pub srgb: bool,
+    pub strict: bool,
+    pub verbosity: Verbosity,
+    pub help: bool,
+}
+
+// Problem with the command line itself, reported along with the usage text
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl CliOptions {
+    pub fn new() -> Self {
+        Self {
+            scenes: Vec::new(),
+            output: None,
+            format: None,
+            resolution: None,
+            max_depth: None,
+            samples: None,
+            threads: 0,
+            frames: None,
+            tone_map: None,
+            exposure: None,
+            srgb: false,
+            strict: false,
+            verbosity: Verbosity::Normal,
+            help: false,
+        }
+    }
+
+    // Image file for a scene, or the base name numbered frames are derived from.
+    // scene_output is the file named by the scene itself.
+    pub fn output_path(&self, scene_path: &str, scene_output: Option <&str>) -> String {
+        let base = match (&self.output, scene_output, self.scenes.len()) {
+            (Some(output), _, _) => output.clone(),
+            (None, Some(output), _) => output.to_string(),
+            (None, None, 1) => "out.png".to_string(),
+            (None, None, _) => {
+                let stem = match scene_path {
+                    "-" => "stdin",
+                    _ => Path::new(scene_path).file_stem().and_then(|s| s.to_str()).unwrap_or("out"),
+                };
+
+                format!("{}.png", stem)
+            },
+        };
+
+        match &self.format {
+            Some(format) => Path::new(&base).with_extension(format).to_string_lossy().into_owned(),
+            None => base,
+        }
+    }
+}
+
+impl Default for CliOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result <T, UsageError> {
+    value.parse::<T>().map_err(|_| UsageError(format!("invalid value \"{}\" for {}", value, option)))
+}
+
+fn parse_resolution(value: &str) -> Result <(usize, usize), UsageError> {
+    let invalid = || UsageError(format!("invalid resolution \"{}\", expected <width>x<height>", value));
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+
+    match (width.parse::<usize>(), height.parse::<usize>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(invalid()),
+    }
+}
+
+// Parses the arguments following the program name
+pub fn parse_args(args: &[String]) -> Result <CliOptions, UsageError> {
+    let mut options = CliOptions::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |count: usize| -> Result <Vec <&String>, UsageError> {
+            let values: Vec <&String> = args.by_ref().take(count).collect();
+
+            if values.len() < count {
+                Err(UsageError(format!("{} expects {} value{}", arg, count, if count == 1 { "" } else { "s" })))
+            }
+            else {
+                Ok(values)
+            }
+        };
+
+        match arg.as_str() {
+            "-o" | "--output" => options.output = Some(value(1)?[0].clone()),
+            "-f" | "--format" => options.format = Some(value(1)?[0].trim_start_matches('.').to_ascii_lowercase()),
+            "-r" | "--resolution" => options.resolution = Some(parse_resolution(value(1)?[0])?),
+            "-d" | "--maxdepth" => options.max_depth = Some(parse_number(arg, value(1)?[0])?),
+            "-s" | "--samples" => options.samples = Some(parse_number(arg, value(1)?[0])?),
+            "-j" | "--threads" => options.threads = parse_number(arg, value(1)?[0])?,
+            "--frames" => {
+                let range = value(2)?;
+                let (first, last) = (parse_number(arg, range[0])?, parse_number(arg, range[1])?);
+
+                if last < first {
+                    return Err(UsageError(format!("last frame {} comes before first frame {} for {}", last, first, arg)));
+                }
+
+                options.frames = Some(FrameRange::new(first, last));
+            },
+            "--tonemap" => {
+                let name = value(1)?[0];
+
+                options.tone_map = Some(ToneMapOperator::from_name(name).ok_or_else(|| UsageError(format!("invalid value \"{}\" for {}", name, arg)))?);
+            },
+            "--exposure" => options.exposure = Some(parse_number(arg, value(1)?[0])?),
+            "--srgb" => options.srgb = true,
+            "--strict" => options.strict = true,
+            "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+            "-h" | "--help" => options.help = true,
+            // Lets scene files start with a dash
+            "--" => options.scenes.extend(args.by_ref().cloned()),
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(UsageError(format!("unknown option {}", arg))),
+            _ => options.scenes.push(arg.clone()),
+        }
+    }
+
+    if options.help {
+        return Ok(options);
+    }
+
+    if options.scenes.is_empty() {
+        return Err(UsageError("no scene files given".to_string()));
+    }
+
+    if options.scenes.iter().filter(|s| *s == "-").count() > 1 {
+        return Err(UsageError("standard input can only be read once".to_string()));
+    }
+
+    if options.output.is_some() && options.scenes.len() > 1 {
+        return Err(UsageError("--output cannot be used with several scene files".to_string()));
+    }
+
+    Ok(options)
+}
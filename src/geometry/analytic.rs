@@ -0,0 +1,1094 @@
+use std::f64::consts::PI;
+
+use crate::primitives::*;
+use crate::raytracer::IntersectData;
+use super::material::Material;
+use super::shapes::{Shape, ShapeDescription, SolidSpan, SurfaceCrossing};
+
+// Object-space ray parameters closer than this are taken to be the ray's own origin
+const MIN_HIT_T: f64 = 1e-7;
+
+// Flat surface through point, extending forever
+#[derive(Debug, Clone, Copy)]
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vector3,
+    pub transform: Matrix4,
+    pub material: Material,
+}
+
+// Box with faces along the object-space axes
+#[derive(Debug, Clone, Copy)]
+pub struct Cuboid {
+    pub min: Point3,
+    pub max: Point3,
+    pub transform: Matrix4,
+    pub material: Material,
+}
+
+// Capped cylinder standing on its base center, with its axis along object-space y
+#[derive(Debug, Clone, Copy)]
+pub struct Cylinder {
+    pub center: Point3,
+    pub radius: f64,
+    pub height: f64,
+    pub transform: Matrix4,
+    pub material: Material,
+}
+
+// Cone with a capped base centered on center and its apex height above it along
+// object-space y
+#[derive(Debug, Clone, Copy)]
+pub struct Cone {
+    pub center: Point3,
+    pub radius: f64,
+    pub height: f64,
+    pub transform: Matrix4,
+    pub material: Material,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vector3,
+    pub radius: f64,
+    pub transform: Matrix4,
+    pub material: Material,
+}
+
+// Ring around the object-space y axis. major_radius is the distance from the center to
+// the middle of the tube, minor_radius the radius of the tube.
+#[derive(Debug, Clone, Copy)]
+pub struct Torus {
+    pub center: Point3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub transform: Matrix4,
+    pub material: Material,
+}
+
+// World-space hit data for a point and unit normal given in object space
+fn object_hit(transform: &Matrix4, material: &Material, face: usize, obj_point: &Point3, obj_normal: &Vector3, uv: (f64, f64), obj_uv_density: f64) -> IntersectData {
+    let normal = transform.mat_invtf_norm_vec3(obj_normal);
+
+    IntersectData {
+        index: 0,
+        time: 0.0,
+        face,
+        coords: *transform * obj_point,
+        normal,
+        geom_normal: normal,
+        uv,
+        uv_density: obj_uv_density / transform_scale(transform),
+        material: *material,
+    }
+}
+
+// Average factor the transform scales lengths by
+fn transform_scale(transform: &Matrix4) -> f64 {
+    transform.determinant3().abs().cbrt()
+}
+
+// World-space point and normal for an object-space sample
+fn world_sample(transform: &Matrix4, obj_point: Point3, obj_normal: Vector3) -> (Point3, Vector3) {
+    (*transform * &obj_point, transform.mat_invtf_norm_vec3(&obj_normal))
+}
+
+// Splits u in [0, 1) between parts with the given weights, returning the part it falls
+// in and u rescaled to [0, 1) within that part
+fn pick_part(u: f64, weights: &[f64]) -> (usize, f64) {
+    let total: f64 = weights.iter().sum();
+    let mut start = 0.0;
+
+    for (i, w) in weights.iter().enumerate() {
+        let end = start + w / total;
+
+        if u < end || i == weights.len() - 1 {
+            return (i, if end > start { ((u - start) / (end - start)).clamp(0.0, 1.0) } else { 0.0 });
+        }
+
+        start = end;
+    }
+
+    (0, u)
+}
+
+// Ray parameter where the ray crosses the plane, None if it runs parallel to it or the
+// crossing is behind the origin
+fn intersect_plane(ray: &Ray, point: &Point3, normal: &Vector3) -> Option <f64> {
+    let divisor = ray.direction.dot(normal);
+
+    if divisor.abs() < 1e-12 {
+        return None;
+    }
+
+    let t = (*point - &ray.position).dot(normal) / divisor;
+
+    if t > MIN_HIT_T { Some(t) } else { None }
+}
+
+// Both roots of a t^2 + b t + c, smallest first
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option <(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+
+        return Some((-c / b, -c / b));
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // Avoids cancellation between -b and the root
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q.abs() < 1e-300 { (0.0, 0.0) } else { (q / a, c / q) };
+
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+impl Plane {
+    pub fn new() -> Self {
+        Self {
+            point: Point3::new_empty(),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+        }
+    }
+
+    // Object-space distances from point along two directions in the plane
+    fn uv_at_object(&self, obj_point: &Point3) -> (f64, f64) {
+        let (t, b) = self.normal.norm().orthonormal_basis();
+        let offset = *obj_point - &self.point;
+
+        (offset.dot(&t), offset.dot(&b))
+    }
+
+    fn hit_at(&self, ray_trans: &Ray, t: f64) -> IntersectData {
+        let obj_point = ray_trans.position + &(ray_trans.direction * t);
+
+        object_hit(&self.transform, &self.material, 0, &obj_point, &self.normal.norm(), self.uv_at_object(&obj_point), 1.0)
+    }
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Plane {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let t = intersect_plane(&ray_trans, &self.point, &self.normal.norm())?;
+
+        Some(self.hit_at(&ray_trans, t))
+    }
+
+    fn normal_at(&self, _point: &Point3, _face: usize) -> Vector3 {
+        self.transform.mat_invtf_norm_vec3(&self.normal.norm())
+    }
+
+    fn uv_at(&self, point: &Point3, _face: usize) -> (f64, f64) {
+        self.uv_at_object(&self.transform.mat_invtf_point3(point))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new_infinite()
+    }
+
+    fn area(&self) -> f64 {
+        f64::INFINITY
+    }
+
+    // Planes have no finite area to spread samples over, so this covers the unit
+    // square of UV space around point
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let normal = self.normal.norm();
+        let (t, b) = normal.orthonormal_basis();
+
+        world_sample(&self.transform, self.point + &(t * (u.0 - 0.5)) + &(b * (u.1 - 0.5)), normal)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // As a solid, a plane is the half-space behind it
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let normal = self.normal.norm();
+        let divisor = ray_trans.direction.dot(&normal);
+        let depth = (self.point - &ray_trans.position).dot(&normal);
+        let unbounded = |t: f64| SurfaceCrossing { t, hit: IntersectData::new() };
+
+        if divisor.abs() < 1e-12 {
+            // Parallel to the plane, entirely inside or outside
+            return if depth > 0.0 { vec![SolidSpan { entry: unbounded(f64::NEG_INFINITY), exit: unbounded(f64::INFINITY) }] } else { Vec::new() };
+        }
+
+        let t = depth / divisor;
+        let crossing = SurfaceCrossing { t, hit: self.hit_at(&ray_trans, t) };
+
+        if divisor < 0.0 {
+            vec![SolidSpan { entry: crossing, exit: unbounded(f64::INFINITY) }]
+        }
+        else {
+            vec![SolidSpan { entry: unbounded(f64::NEG_INFINITY), exit: crossing }]
+        }
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Plane(self))
+    }
+}
+
+impl Cuboid {
+    pub fn new() -> Self {
+        Self {
+            min: Point3::new_empty(),
+            max: Point3::new_with_value(1.0),
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+        }
+    }
+
+    // Faces are numbered 2 * axis for the side at min and 2 * axis + 1 for the side at max
+    fn face_normal(face: usize) -> Vector3 {
+        let mut normal = Vector3::new_empty();
+        normal[face / 2] = if face.is_multiple_of(2) { -1.0 } else { 1.0 };
+
+        normal
+    }
+
+    fn face_axes(face: usize) -> (usize, usize) {
+        ((face / 2 + 1) % 3, (face / 2 + 2) % 3)
+    }
+
+    fn face_area(&self, face: usize) -> f64 {
+        let (a, b) = Self::face_axes(face);
+
+        (self.max[a] - self.min[a]) * (self.max[b] - self.min[b])
+    }
+
+    // Position across the face, scaled to the unit square
+    fn uv_at_object(&self, obj_point: &Point3, face: usize) -> (f64, f64) {
+        let (a, b) = Self::face_axes(face);
+
+        (
+            (obj_point[a] - self.min[a]) / (self.max[a] - self.min[a]),
+            (obj_point[b] - self.min[b]) / (self.max[b] - self.min[b]),
+        )
+    }
+
+    // Ray parameters and faces where the ray's line enters and leaves the box
+    fn object_slabs(&self, ray_trans: &Ray) -> Option <((f64, usize), (f64, usize))> {
+        let mut near = (f64::NEG_INFINITY, 0);
+        let mut far = (f64::INFINITY, 0);
+
+        for axis in 0..3 {
+            let inv_dir = 1.0 / ray_trans.direction[axis];
+            let t_min = (self.min[axis] - ray_trans.position[axis]) * inv_dir;
+            let t_max = (self.max[axis] - ray_trans.position[axis]) * inv_dir;
+
+            // Faces the ray enters and leaves through along this axis
+            let (t_enter, t_leave) = if inv_dir >= 0.0 {
+                ((t_min, 2 * axis), (t_max, 2 * axis + 1))
+            }
+            else {
+                ((t_max, 2 * axis + 1), (t_min, 2 * axis))
+            };
+
+            if t_enter.0 > near.0 {
+                near = t_enter;
+            }
+
+            if t_leave.0 < far.0 {
+                far = t_leave;
+            }
+        }
+
+        if near.0 > far.0 { None } else { Some((near, far)) }
+    }
+
+    fn hit_at(&self, ray_trans: &Ray, t: f64, face: usize) -> IntersectData {
+        let obj_point = ray_trans.position + &(ray_trans.direction * t);
+        let uv_density = 1.0 / self.face_area(face).sqrt();
+
+        object_hit(&self.transform, &self.material, face, &obj_point, &Self::face_normal(face), self.uv_at_object(&obj_point, face), uv_density)
+    }
+}
+
+impl Default for Cuboid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Cuboid {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let (near, far) = self.object_slabs(&ray_trans)?;
+
+        // Rays starting inside hit the face they leave through
+        let (t, face) = if near.0 > MIN_HIT_T {
+            near
+        }
+        else if far.0 > MIN_HIT_T {
+            far
+        }
+        else {
+            return None;
+        };
+
+        Some(self.hit_at(&ray_trans, t, face))
+    }
+
+    fn normal_at(&self, _point: &Point3, face: usize) -> Vector3 {
+        self.transform.mat_invtf_norm_vec3(&Self::face_normal(face))
+    }
+
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        self.uv_at_object(&self.transform.mat_invtf_point3(point), face)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(self.min, self.max).transform(&self.transform)
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = transform_scale(&self.transform);
+
+        (0..6).map(|f| self.face_area(f)).sum::<f64>() * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let areas: Vec <f64> = (0..6).map(|f| self.face_area(f)).collect();
+        let (face, u_face) = pick_part(u.0, &areas);
+        let (a, b) = Self::face_axes(face);
+
+        let mut obj_point = if face.is_multiple_of(2) { self.min } else { self.max };
+        obj_point[a] = self.min[a] + u_face * (self.max[a] - self.min[a]);
+        obj_point[b] = self.min[b] + u.1 * (self.max[b] - self.min[b]);
+
+        world_sample(&self.transform, obj_point, Self::face_normal(face))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        match self.object_slabs(&ray_trans) {
+            Some(((t_near, f_near), (t_far, f_far))) => vec![SolidSpan {
+                entry: SurfaceCrossing { t: t_near, hit: self.hit_at(&ray_trans, t_near, f_near) },
+                exit: SurfaceCrossing { t: t_far, hit: self.hit_at(&ray_trans, t_far, f_far) },
+            }],
+            None => Vec::new(),
+        }
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Box(self))
+    }
+}
+
+// Texture coordinates on a cap of the given radius: the disk is inscribed in the unit
+// square, seen from above
+fn cap_uv(local: &Vector3, radius: f64) -> (f64, f64) {
+    (0.5 + local[0] / (2.0 * radius), 0.5 + local[2] / (2.0 * radius))
+}
+
+// Angle around the y axis, scaled to [0, 1)
+fn longitude(local: &Vector3) -> f64 {
+    0.5 + local[2].atan2(local[0]) / (2.0 * PI)
+}
+
+// Point in the xz-plane disk of the given radius, spread evenly over its area
+fn sample_disk(radius: f64, u: (f64, f64)) -> (f64, f64) {
+    let (r, phi) = (radius * u.0.sqrt(), 2.0 * PI * u.1);
+
+    (r * phi.cos(), r * phi.sin())
+}
+
+// Crossings of the ray's line with the side of a y-axis surface of revolution and its
+// caps, sorted along the ray. side gives the roots of the side's quadratic; caps lists
+// the height and radius of each cap. Faces are numbered as for Cylinder.
+fn revolved_crossings<F>(ray_trans: &Ray, center: &Point3, height: f64, side: F, caps: &[(f64, f64)]) -> Vec <(f64, usize)>
+where F: Fn(&Vector3, &Vector3) -> Option <(f64, f64)>, {
+    let origin = ray_trans.position - center;
+    let direction = ray_trans.direction;
+    let mut crossings: Vec <(f64, usize)> = Vec::new();
+
+    if let Some((t0, t1)) = side(&origin, &direction) {
+        for t in [t0, t1] {
+            let y = origin[1] + direction[1] * t;
+
+            if (0.0..=height).contains(&y) {
+                crossings.push((t, 0));
+            }
+        }
+    }
+
+    if direction[1].abs() > 1e-12 {
+        for (i, (cap_y, cap_radius)) in caps.iter().enumerate() {
+            let t = (cap_y - origin[1]) / direction[1];
+            let (x, z) = (origin[0] + direction[0] * t, origin[2] + direction[2] * t);
+
+            if x * x + z * z <= cap_radius * cap_radius {
+                crossings.push((t, i + 1));
+            }
+        }
+    }
+
+    crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+    crossings
+}
+
+// Spans for a closed surface from its sorted crossings with the ray's line
+fn spans_from_roots<F>(ray: &Ray, roots: &[(f64, usize)], hit_at: F) -> Vec <SolidSpan>
+where F: Fn(f64, usize) -> IntersectData, {
+    let crossings: Vec <SurfaceCrossing> = roots.iter().map(|&(t, face)| SurfaceCrossing { t, hit: hit_at(t, face) }).collect();
+
+    SolidSpan::from_crossings(ray, &crossings)
+}
+
+impl Cylinder {
+    pub fn new() -> Self {
+        Self {
+            center: Point3::new_empty(),
+            radius: 1.0,
+            height: 1.0,
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+        }
+    }
+
+    // Face 0 is the side, 1 the bottom cap and 2 the top cap
+    fn object_normal(&self, local: &Vector3, face: usize) -> Vector3 {
+        match face {
+            0 => Vector3::new(local[0], 0.0, local[2]).norm(),
+            1 => Vector3::new(0.0, -1.0, 0.0),
+            _ => Vector3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    fn object_uv(&self, local: &Vector3, face: usize) -> (f64, f64) {
+        match face {
+            0 => (longitude(local), local[1] / self.height),
+            _ => cap_uv(local, self.radius),
+        }
+    }
+
+    fn object_areas(&self) -> [f64; 3] {
+        let cap = PI * self.radius * self.radius;
+
+        [2.0 * PI * self.radius * self.height, cap, cap]
+    }
+
+    fn object_crossings(&self, ray_trans: &Ray) -> Vec <(f64, usize)> {
+        let side = |o: &Vector3, d: &Vector3| {
+            solve_quadratic(d[0] * d[0] + d[2] * d[2], 2.0 * (o[0] * d[0] + o[2] * d[2]), o[0] * o[0] + o[2] * o[2] - self.radius * self.radius)
+        };
+
+        revolved_crossings(ray_trans, &self.center, self.height, side, &[(0.0, self.radius), (self.height, self.radius)])
+    }
+
+    fn hit_at(&self, ray_trans: &Ray, t: f64, face: usize) -> IntersectData {
+        let obj_point = ray_trans.position + &(ray_trans.direction * t);
+        let local = obj_point - &self.center;
+        let uv_density = 1.0 / self.object_areas()[face].sqrt();
+
+        object_hit(&self.transform, &self.material, face, &obj_point, &self.object_normal(&local, face), self.object_uv(&local, face), uv_density)
+    }
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Cylinder {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        self.object_crossings(&ray_trans).into_iter()
+            .find(|(t, _)| *t > MIN_HIT_T)
+            .map(|(t, face)| self.hit_at(&ray_trans, t, face))
+    }
+
+    fn normal_at(&self, point: &Point3, face: usize) -> Vector3 {
+        let local = self.transform.mat_invtf_point3(point) - &self.center;
+
+        self.transform.mat_invtf_norm_vec3(&self.object_normal(&local, face))
+    }
+
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        self.object_uv(&(self.transform.mat_invtf_point3(point) - &self.center), face)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let extent = Vector3::new(self.radius, 0.0, self.radius);
+        let top = self.center + &Vector3::new(0.0, self.height, 0.0);
+
+        BoundingBox::new(self.center - &extent, top + &extent).transform(&self.transform)
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = transform_scale(&self.transform);
+
+        self.object_areas().iter().sum::<f64>() * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let (face, u_face) = pick_part(u.0, &self.object_areas());
+
+        let local = match face {
+            0 => {
+                let phi = 2.0 * PI * u_face;
+                Vector3::new(self.radius * phi.cos(), u.1 * self.height, self.radius * phi.sin())
+            },
+            _ => {
+                let (x, z) = sample_disk(self.radius, (u_face, u.1));
+                Vector3::new(x, if face == 1 { 0.0 } else { self.height }, z)
+            },
+        };
+
+        world_sample(&self.transform, self.center + &local, self.object_normal(&local, face))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        spans_from_roots(ray, &self.object_crossings(&ray_trans), |t, face| self.hit_at(&ray_trans, t, face))
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Cylinder(self))
+    }
+}
+
+impl Cone {
+    pub fn new() -> Self {
+        Self {
+            center: Point3::new_empty(),
+            radius: 1.0,
+            height: 1.0,
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+        }
+    }
+
+    fn slope(&self) -> f64 {
+        self.radius / self.height
+    }
+
+    // Face 0 is the side and 1 the base
+    fn object_normal(&self, local: &Vector3, face: usize) -> Vector3 {
+        if face != 0 {
+            return Vector3::new(0.0, -1.0, 0.0);
+        }
+
+        let ring_radius = (local[0] * local[0] + local[2] * local[2]).sqrt();
+
+        // The normal is undefined at the apex
+        if ring_radius < 1e-12 {
+            return Vector3::new(0.0, 1.0, 0.0);
+        }
+
+        Vector3::new(local[0], self.slope() * ring_radius, local[2]).norm()
+    }
+
+    fn object_uv(&self, local: &Vector3, face: usize) -> (f64, f64) {
+        match face {
+            0 => (longitude(local), local[1] / self.height),
+            _ => cap_uv(local, self.radius),
+        }
+    }
+
+    fn object_areas(&self) -> [f64; 2] {
+        let slant = (self.radius * self.radius + self.height * self.height).sqrt();
+
+        [PI * self.radius * slant, PI * self.radius * self.radius]
+    }
+
+    fn object_crossings(&self, ray_trans: &Ray) -> Vec <(f64, usize)> {
+        let k = self.slope();
+
+        // Radius shrinks linearly from the base to the apex: x^2 + z^2 = (radius - k y)^2
+        let side = |o: &Vector3, d: &Vector3| {
+            let ring = self.radius - k * o[1];
+
+            solve_quadratic(
+                d[0] * d[0] + d[2] * d[2] - k * k * d[1] * d[1],
+                2.0 * (o[0] * d[0] + o[2] * d[2]) + 2.0 * k * d[1] * ring,
+                o[0] * o[0] + o[2] * o[2] - ring * ring,
+            )
+        };
+
+        revolved_crossings(ray_trans, &self.center, self.height, side, &[(0.0, self.radius)])
+    }
+
+    fn hit_at(&self, ray_trans: &Ray, t: f64, face: usize) -> IntersectData {
+        let obj_point = ray_trans.position + &(ray_trans.direction * t);
+        let local = obj_point - &self.center;
+        let uv_density = 1.0 / self.object_areas()[face].sqrt();
+
+        object_hit(&self.transform, &self.material, face, &obj_point, &self.object_normal(&local, face), self.object_uv(&local, face), uv_density)
+    }
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Cone {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        self.object_crossings(&ray_trans).into_iter()
+            .find(|(t, _)| *t > MIN_HIT_T)
+            .map(|(t, face)| self.hit_at(&ray_trans, t, face))
+    }
+
+    fn normal_at(&self, point: &Point3, face: usize) -> Vector3 {
+        let local = self.transform.mat_invtf_point3(point) - &self.center;
+
+        self.transform.mat_invtf_norm_vec3(&self.object_normal(&local, face))
+    }
+
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        self.object_uv(&(self.transform.mat_invtf_point3(point) - &self.center), face)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let extent = Vector3::new(self.radius, 0.0, self.radius);
+        let top = self.center + &Vector3::new(0.0, self.height, 0.0);
+
+        BoundingBox::new(self.center - &extent, top + &extent).transform(&self.transform)
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = transform_scale(&self.transform);
+
+        self.object_areas().iter().sum::<f64>() * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let (face, u_face) = pick_part(u.0, &self.object_areas());
+
+        // Both the side and the base have area growing linearly with the distance from
+        // their center, so the same disk mapping spreads samples evenly on either
+        let (x, z) = sample_disk(self.radius, (u_face, u.1));
+        let y = if face == 0 { self.height * (1.0 - u_face.sqrt()) } else { 0.0 };
+        let local = Vector3::new(x, y, z);
+
+        world_sample(&self.transform, self.center + &local, self.object_normal(&local, face))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        spans_from_roots(ray, &self.object_crossings(&ray_trans), |t, face| self.hit_at(&ray_trans, t, face))
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Cone(self))
+    }
+}
+
+impl Disk {
+    pub fn new() -> Self {
+        Self {
+            center: Point3::new_empty(),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            radius: 1.0,
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+        }
+    }
+
+    // Position across the disk, which is inscribed in the unit square
+    fn uv_at_object(&self, obj_point: &Point3) -> (f64, f64) {
+        let (t, b) = self.normal.norm().orthonormal_basis();
+        let offset = *obj_point - &self.center;
+
+        (0.5 + offset.dot(&t) / (2.0 * self.radius), 0.5 + offset.dot(&b) / (2.0 * self.radius))
+    }
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Disk {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let normal = self.normal.norm();
+        let t = intersect_plane(&ray_trans, &self.center, &normal)?;
+        let obj_point = ray_trans.position + &(ray_trans.direction * t);
+        let offset = obj_point - &self.center;
+
+        if offset.dot(&offset) > self.radius * self.radius {
+            return None;
+        }
+
+        Some(object_hit(&self.transform, &self.material, 0, &obj_point, &normal, self.uv_at_object(&obj_point), 1.0 / (2.0 * self.radius)))
+    }
+
+    fn normal_at(&self, _point: &Point3, _face: usize) -> Vector3 {
+        self.transform.mat_invtf_norm_vec3(&self.normal.norm())
+    }
+
+    fn uv_at(&self, point: &Point3, _face: usize) -> (f64, f64) {
+        self.uv_at_object(&self.transform.mat_invtf_point3(point))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let normal = self.normal.norm();
+        let (t, b) = normal.orthonormal_basis();
+        let rim: Vec <Point3> = [(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)].iter()
+            .map(|(st, sb)| self.center + &(t * (st * self.radius)) + &(b * (sb * self.radius)))
+            .collect();
+
+        BoundingBox::from_points(&rim).transform(&self.transform)
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = transform_scale(&self.transform);
+
+        PI * self.radius * self.radius * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let normal = self.normal.norm();
+        let (t, b) = normal.orthonormal_basis();
+        let (x, y) = sample_disk(self.radius, u);
+
+        world_sample(&self.transform, self.center + &(t * x) + &(b * y), normal)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Disk(self))
+    }
+}
+
+// Real roots of c[2] x^2 + c[1] x + c[0]
+fn solve_quadric(c: [f64; 3]) -> Vec <f64> {
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let discriminant = p * p - q;
+
+    if discriminant.abs() < 1e-12 {
+        vec![-p]
+    }
+    else if discriminant < 0.0 {
+        Vec::new()
+    }
+    else {
+        let sqrt_d = discriminant.sqrt();
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+// Real roots of c[3] x^3 + c[2] x^2 + c[1] x + c[0], by Cardano's formula
+fn solve_cubic(c: [f64; 4]) -> Vec <f64> {
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let cc = c[0] / c[3];
+
+    // Substitute x = y - a / 3 to remove the quadratic term: y^3 + 3 p y + 2 q = 0
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + cc) / 2.0;
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+
+    let roots = if discriminant.abs() < 1e-12 {
+        if q.abs() < 1e-12 {
+            vec![0.0]
+        }
+        else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    }
+    else if discriminant < 0.0 {
+        // Three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    }
+    else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    roots.into_iter().map(|y| y - a / 3.0).collect()
+}
+
+// Real roots of the quartic with coefficients c[i] for x^i, by Ferrari's method
+fn solve_quartic(c: [f64; 5]) -> Vec <f64> {
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let cc = c[1] / c[4];
+    let d = c[0] / c[4];
+
+    // Substitute x = y - a / 4 to remove the cubic term: y^4 + p y^2 + q y + r = 0
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + cc;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * cc / 4.0 + d;
+
+    let roots = if r.abs() < 1e-12 {
+        // y (y^3 + p y + q) = 0
+        let mut roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    }
+    else {
+        // Any root of the resolvent cubic splits the quartic into two quadratics
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+
+        let u = if u.abs() < 1e-12 { 0.0 } else if u > 0.0 { u.sqrt() } else { return Vec::new() };
+        let v = if v.abs() < 1e-12 { 0.0 } else if v > 0.0 { v.sqrt() } else { return Vec::new() };
+
+        let mut roots = solve_quadric([z - u, if q < 0.0 { -v } else { v }, 1.0]);
+        roots.extend(solve_quadric([z + u, if q < 0.0 { v } else { -v }, 1.0]));
+        roots
+    };
+
+    roots.into_iter().map(|y| {
+        // Polish against the original polynomial, since the closed form loses precision
+        let mut x = y - a / 4.0;
+
+        for _ in 0..2 {
+            let f = (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+            let df = ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+
+            if df.abs() > 1e-300 {
+                x -= f / df;
+            }
+        }
+
+        x
+    }).collect()
+}
+
+impl Torus {
+    pub fn new() -> Self {
+        Self {
+            center: Point3::new_empty(),
+            major_radius: 1.0,
+            minor_radius: 0.25,
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+        }
+    }
+
+    // Ray parameters where the ray's line crosses the torus, sorted along it
+    fn object_roots(&self, ray_trans: &Ray) -> Vec <f64> {
+        let (big_r, small_r) = (self.major_radius, self.minor_radius);
+        let dir_len = ray_trans.direction.len();
+        let direction = ray_trans.direction / dir_len;
+        let origin = ray_trans.position - &self.center;
+
+        // Start from where the ray enters the bounding sphere, so that the quartic is
+        // solved close to the torus where it is well conditioned
+        let bound_r = big_r + small_r;
+        let f = origin.dot(&direction);
+        let bound_disc = f * f - (origin.dot(&origin) - bound_r * bound_r);
+
+        if bound_disc < 0.0 {
+            return Vec::new();
+        }
+
+        let t_shift = -f - bound_disc.sqrt();
+        let p = origin + &(direction * t_shift);
+
+        // (|p|^2 - R^2 - r^2)^2 = 4 R^2 (r^2 - y^2) along the ray
+        let four_r2 = 4.0 * big_r * big_r;
+        let e = p.dot(&p) - big_r * big_r - small_r * small_r;
+        let f = p.dot(&direction);
+
+        let coeffs = [
+            e * e - four_r2 * (small_r * small_r - p[1] * p[1]),
+            4.0 * f * e + 2.0 * four_r2 * p[1] * direction[1],
+            2.0 * e + 4.0 * f * f + four_r2 * direction[1] * direction[1],
+            4.0 * f,
+            1.0,
+        ];
+
+        let mut roots: Vec <f64> = solve_quartic(coeffs).into_iter().map(|t| (t + t_shift) / dir_len).collect();
+        roots.sort_by(|a, b| a.total_cmp(b));
+
+        roots
+    }
+
+    // Nearest hit further than min_t along the ray, given in object-space units
+    fn intersect_object(&self, ray_trans: &Ray, min_t: f64) -> Option <IntersectData> {
+        let min_t = min_t / ray_trans.direction.len();
+
+        self.object_roots(ray_trans).into_iter()
+            .find(|t| *t > min_t)
+            .map(|t| self.hit(&(ray_trans.position + &(ray_trans.direction * t))))
+    }
+
+    fn object_normal(&self, local: &Vector3) -> Vector3 {
+        // Away from the nearest point on the circle through the middle of the tube
+        let ring = Vector3::new(local[0], 0.0, local[2]);
+        let ring_len = ring.len();
+
+        if ring_len < 1e-12 {
+            return Vector3::new(0.0, local[1].signum(), 0.0);
+        }
+
+        (*local - &(ring * (self.major_radius / ring_len))).norm()
+    }
+
+    // Angle around the y axis and angle around the tube
+    fn object_uv(&self, local: &Vector3) -> (f64, f64) {
+        let ring_dist = (local[0] * local[0] + local[2] * local[2]).sqrt() - self.major_radius;
+
+        (longitude(local), 0.5 + local[1].atan2(ring_dist) / (2.0 * PI))
+    }
+
+    fn hit(&self, obj_point: &Point3) -> IntersectData {
+        let local = *obj_point - &self.center;
+        let uv_density = 1.0 / (2.0 * PI * (self.major_radius * self.minor_radius).sqrt());
+
+        object_hit(&self.transform, &self.material, 0, obj_point, &self.object_normal(&local), self.object_uv(&local), uv_density)
+    }
+}
+
+impl Default for Torus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Torus {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        self.intersect_object(&ray_trans, MIN_HIT_T)
+    }
+
+    // The torus is not convex, so rays leaving it can hit it again elsewhere
+    fn intersect_from_face(&self, ray: &Ray, _face: usize) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let min_t = 1e-6 * (self.major_radius + self.minor_radius);
+
+        self.intersect_object(&ray_trans, min_t)
+    }
+
+    fn normal_at(&self, point: &Point3, _face: usize) -> Vector3 {
+        let local = self.transform.mat_invtf_point3(point) - &self.center;
+
+        self.transform.mat_invtf_norm_vec3(&self.object_normal(&local))
+    }
+
+    fn uv_at(&self, point: &Point3, _face: usize) -> (f64, f64) {
+        self.object_uv(&(self.transform.mat_invtf_point3(point) - &self.center))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vector3::new(outer, self.minor_radius, outer);
+
+        BoundingBox::new(self.center - &extent, self.center + &extent).transform(&self.transform)
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = transform_scale(&self.transform);
+
+        4.0 * PI * PI * self.major_radius * self.minor_radius * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let (big_r, small_r) = (self.major_radius, self.minor_radius);
+        let phi = 2.0 * PI * u.0;
+
+        // The outside of the tube has more area than the inside: invert the cumulative
+        // distribution (theta + k sin theta) / 2 pi with Newton's method
+        let k = small_r / big_r;
+        let target = 2.0 * PI * u.1;
+        let mut theta = target;
+
+        for _ in 0..8 {
+            theta -= (theta + k * theta.sin() - target) / (1.0 + k * theta.cos());
+        }
+
+        let ring = big_r + small_r * theta.cos();
+        let local = Vector3::new(ring * phi.cos(), small_r * theta.sin(), ring * phi.sin());
+
+        world_sample(&self.transform, self.center + &local, self.object_normal(&local))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let roots: Vec <(f64, usize)> = self.object_roots(&ray_trans).into_iter().map(|t| (t, 0)).collect();
+
+        spans_from_roots(ray, &roots, |t, _| self.hit(&(ray_trans.position + &(ray_trans.direction * t))))
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Torus(self))
+    }
+}
@@ -0,0 +1,290 @@
+use crate::primitives::*;
+use crate::raytracer::IntersectData;
+use super::material::Material;
+use super::shapes::{Shape, ShapeDescription, SolidSpan, SurfaceCrossing};
+
+// Ray parameters closer than this are taken to be the ray's own origin
+const MIN_HIT_T: f64 = 1e-7;
+
+// Crossings this close to the origin of a ray leaving the same face are that face again
+const SAME_FACE_T: f64 = 1e-6;
+
+// Attempts at finding a point on the combined surface before sample gives up
+const SAMPLE_ATTEMPTS: usize = 32;
+
+// Samples per axis for each operand when estimating the combined surface area
+const AREA_GRID: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp {
+    // Inside any operand
+    Union,
+    // Inside every operand
+    Intersection,
+    // Inside the first operand but none of the others
+    Difference,
+}
+
+// Boolean combination of solids. Each part of the surface keeps the material of the
+// operand it comes from, and surfaces carved out by a difference face into the hole.
+// Faces are numbered face * operands.len() + operand, after the operand's own face.
+#[derive(Debug)]
+pub struct Csg {
+    pub op: CsgOp,
+    pub operands: Vec <Box <dyn Shape>>,
+}
+
+impl CsgOp {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "union" => Some(CsgOp::Union),
+            "intersection" => Some(CsgOp::Intersection),
+            "difference" => Some(CsgOp::Difference),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsgOp::Union => "union",
+            CsgOp::Intersection => "intersection",
+            CsgOp::Difference => "difference",
+        }
+    }
+
+    fn contains(&self, inside: &[bool]) -> bool {
+        match self {
+            CsgOp::Union => inside.iter().any(|i| *i),
+            CsgOp::Intersection => inside.iter().all(|i| *i),
+            CsgOp::Difference => inside[0] && !inside[1..].iter().any(|i| *i),
+        }
+    }
+}
+
+// Whether a point lies inside a solid, by checking the spans of a line through it
+fn solid_contains(shape: &dyn Shape, point: &Point3) -> bool {
+    let ray = Ray {
+        position: *point,
+        // Skewed so that it is unlikely to run along the faces of axis-aligned shapes
+        direction: Vector3::new(0.5773, 0.6184, 0.5331),
+        time: 0.0,
+    };
+
+    shape.solid_spans(&ray).iter().any(|s| s.entry.t < 0.0 && s.exit.t > 0.0)
+}
+
+// Sample weights by area, counting unbounded operands as having none
+fn area_weights(areas: &[f64]) -> Vec <f64> {
+    let weights: Vec <f64> = areas.iter().map(|a| if a.is_finite() { *a } else { 0.0 }).collect();
+
+    if weights.iter().sum::<f64>() > 0.0 { weights } else { vec![1.0; areas.len()] }
+}
+
+impl Csg {
+    pub fn new(op: CsgOp) -> Self {
+        Self {
+            op,
+            operands: Vec::new(),
+        }
+    }
+
+    pub fn push<S: Shape + 'static>(&mut self, shape: S) {
+        self.operands.push(Box::new(shape));
+    }
+
+    // Whether the surface of the given operand faces the opposite way on the combined
+    // solid, as for the holes a difference carves
+    fn flips(&self, operand: usize) -> bool {
+        self.op == CsgOp::Difference && operand > 0
+    }
+
+    fn split_face(&self, face: usize) -> (usize, usize) {
+        (face % self.operands.len(), face / self.operands.len())
+    }
+
+    // Crossings of the combined surface along the ray's line, in order along it
+    fn crossings(&self, ray: &Ray) -> Vec <SurfaceCrossing> {
+        let count = self.operands.len();
+        let mut events: Vec <(SurfaceCrossing, usize, bool)> = Vec::new();
+
+        for (i, operand) in self.operands.iter().enumerate() {
+            for span in operand.solid_spans(ray) {
+                events.push((span.entry, i, true));
+                events.push((span.exit, i, false));
+            }
+        }
+
+        events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let mut inside = vec![false; count];
+        let mut was_inside = false;
+        let mut crossings: Vec <SurfaceCrossing> = Vec::new();
+
+        for (crossing, i, entering) in events {
+            inside[i] = entering;
+            let now_inside = self.op.contains(&inside);
+
+            if now_inside != was_inside {
+                let mut crossing = crossing;
+                crossing.hit.face = crossing.hit.face * count + i;
+
+                if self.flips(i) {
+                    crossing.hit.normal = crossing.hit.normal * -1.0;
+                    crossing.hit.geom_normal = crossing.hit.geom_normal * -1.0;
+                }
+
+                crossings.push(crossing);
+                was_inside = now_inside;
+            }
+        }
+
+        crossings
+    }
+
+    // Whether a point on the surface of the given operand is part of the combined surface
+    fn on_surface(&self, operand: usize, point: &Point3) -> bool {
+        let mut others = self.operands.iter().enumerate()
+            .filter(|(i, _)| *i != operand)
+            .map(|(i, o)| (i, solid_contains(o.as_ref(), point)));
+
+        match self.op {
+            CsgOp::Union => !others.any(|(_, inside)| inside),
+            CsgOp::Intersection => others.all(|(_, inside)| inside),
+            CsgOp::Difference => others.all(|(i, inside)| if i == 0 { inside } else { !inside }),
+        }
+    }
+
+    // Point on the surface of an operand picked by area, with the normal facing out of
+    // the combined solid
+    fn sample_operands(&self, u: (f64, f64), weights: &[f64]) -> (usize, Point3, Vector3) {
+        let total: f64 = weights.iter().sum();
+        let mut start = 0.0;
+        let mut picked = (weights.len() - 1, 0.0);
+
+        for (i, w) in weights.iter().enumerate() {
+            let end = start + w / total;
+
+            if u.0 < end {
+                picked = (i, if end > start { (u.0 - start) / (end - start) } else { 0.0 });
+                break;
+            }
+
+            start = end;
+        }
+
+        let (i, u_operand) = picked;
+        let (point, normal) = self.operands[i].sample((u_operand.clamp(0.0, 1.0), u.1));
+
+        (i, point, if self.flips(i) { normal * -1.0 } else { normal })
+    }
+}
+
+impl Shape for Csg {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        self.crossings(ray).into_iter()
+            .find(|c| c.t > MIN_HIT_T && c.t.is_finite())
+            .map(|c| c.hit)
+    }
+
+    // Operands need not be convex and carved surfaces face inwards, so rays leaving the
+    // combined surface can hit it again
+    fn intersect_from_face(&self, ray: &Ray, face: usize) -> Option <IntersectData> {
+        self.crossings(ray).into_iter()
+            .find(|c| c.t > MIN_HIT_T && c.t.is_finite() && !(c.hit.face == face && c.t < SAME_FACE_T))
+            .map(|c| c.hit)
+    }
+
+    fn normal_at(&self, point: &Point3, face: usize) -> Vector3 {
+        let (i, operand_face) = self.split_face(face);
+        let normal = self.operands[i].normal_at(point, operand_face);
+
+        if self.flips(i) { normal * -1.0 } else { normal }
+    }
+
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        let (i, operand_face) = self.split_face(face);
+
+        self.operands[i].uv_at(point, operand_face)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let mut all_bounds = self.operands.iter().map(|o| o.bounds());
+
+        match self.op {
+            CsgOp::Union => all_bounds.fold(BoundingBox::new_empty(), |acc, b| acc.union(&b)),
+            CsgOp::Intersection => all_bounds.fold(BoundingBox::new_infinite(), |acc, b| {
+                let mut overlap = acc;
+
+                for i in 0..3 {
+                    overlap.min[i] = acc.min[i].max(b.min[i]);
+                    overlap.max[i] = acc.max[i].min(b.max[i]);
+                }
+
+                overlap
+            }),
+            CsgOp::Difference => all_bounds.next().unwrap_or(BoundingBox::new_empty()),
+        }
+    }
+
+    // Estimated from how much of each operand's surface is left on a grid of samples
+    fn area(&self) -> f64 {
+        self.operands.iter().enumerate().map(|(i, operand)| {
+            let area = operand.area();
+            let mut kept = 0;
+
+            if area == 0.0 || !area.is_finite() {
+                return area;
+            }
+
+            for x in 0..AREA_GRID {
+                for y in 0..AREA_GRID {
+                    let u = ((x as f64 + 0.5) / AREA_GRID as f64, (y as f64 + 0.5) / AREA_GRID as f64);
+
+                    if self.on_surface(i, &operand.sample(u).0) {
+                        kept += 1;
+                    }
+                }
+            }
+
+            area * (kept as f64) / ((AREA_GRID * AREA_GRID) as f64)
+        }).sum()
+    }
+
+    // Samples operands by their full area and retries with shifted u until the point is
+    // on the combined surface, so the spread is only roughly even
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let weights = area_weights(&self.operands.iter().map(|o| o.area()).collect::<Vec <f64>>());
+        let mut sample = self.sample_operands(u, &weights);
+
+        for attempt in 1..SAMPLE_ATTEMPTS {
+            if self.on_surface(sample.0, &sample.1) {
+                break;
+            }
+
+            // Additive recurrence with the plastic number, covering the unit square evenly
+            let shift = (attempt as f64 * 0.754_877_666_2, attempt as f64 * 0.569_840_290_9);
+            sample = self.sample_operands(((u.0 + shift.0).fract(), (u.1 + shift.1).fract()), &weights);
+        }
+
+        (sample.1, sample.2)
+    }
+
+    fn material(&self) -> &Material {
+        self.operands[0].material()
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        // Crossings of the combined surface alternate between entering and leaving it
+        let crossings = self.crossings(ray);
+
+        crossings.chunks_exact(2).map(|c| SolidSpan { entry: c[0], exit: c[1] }).collect()
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Csg(self))
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use crate::primitives::*;
+use crate::raytracer::{Bvh, IntersectData};
+use super::material::Material;
+use super::shapes::{Shape, ShapeDescription, Shapes};
+
+// Named group of shapes with its own acceleration structure, placed in the scene by
+// any number of instances that share it. The shapes' transforms place them relative to
+// the object.
+#[derive(Debug)]
+pub struct ObjectDefinition {
+    pub name: String,
+    pub shapes: Shapes,
+
+    bvh: Bvh,
+    bounds: BoundingBox,
+    // Running total of shape areas, counting unbounded shapes as having none
+    area_cdf: Vec <f64>,
+}
+
+// An object definition placed in the scene with a transform. Unless override_material
+// is set, every shape keeps the material it was defined with.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub object: Arc <ObjectDefinition>,
+    pub transform: Matrix4,
+    pub material: Material,
+    pub override_material: bool,
+}
+
+impl ObjectDefinition {
+    pub fn new(name: &str, shapes: Shapes) -> Self {
+        let shape_bounds: Vec <BoundingBox> = shapes.0.iter().map(|s| s.bounds()).collect();
+        let bounds = shape_bounds.iter().fold(BoundingBox::new_empty(), |acc, b| if b.is_empty() { acc } else { acc.union(b) });
+
+        let mut area_sum = 0.0;
+        let area_cdf = shapes.0.iter().map(|s| {
+            let area = s.area();
+            area_sum += if area.is_finite() { area } else { 0.0 };
+            area_sum
+        }).collect();
+
+        Self {
+            name: name.to_string(),
+            bvh: Bvh::build(&shape_bounds),
+            shapes,
+            bounds,
+            area_cdf,
+        }
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    // Object-space bounds, infinite when the object has an unbounded shape
+    pub fn bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    // Shape picked with probability proportional to its area, along with u rescaled to
+    // [0, 1) within that shape's share of the range
+    fn pick_shape(&self, u: f64) -> (usize, f64) {
+        let total = match self.area_cdf.last() {
+            Some(t) if *t > 0.0 => *t,
+            _ => return (0, u),
+        };
+
+        let target = u * total;
+        let shape = self.area_cdf.partition_point(|a| *a <= target).min(self.area_cdf.len() - 1);
+        let start = if shape > 0 { self.area_cdf[shape - 1] } else { 0.0 };
+        let shape_area = self.area_cdf[shape] - start;
+
+        (shape, if shape_area > 0.0 { ((target - start) / shape_area).clamp(0.0, 1.0) } else { 0.0 })
+    }
+}
+
+impl Instance {
+    pub fn new(object: Arc <ObjectDefinition>) -> Self {
+        Self {
+            object,
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+            override_material: false,
+        }
+    }
+
+    // Faces are numbered face * shapes.len() + shape, after the shape's own face
+    fn split_face(&self, face: usize) -> (usize, usize) {
+        let count = self.object.shapes.0.len().max(1);
+
+        (face % count, face / count)
+    }
+
+    fn intersect_shapes(&self, ray: &Ray, skip_face: Option <usize>) -> Option <IntersectData> {
+        let ray_obj = self.transform.mat_invtf_ray(ray);
+        let count = self.object.shapes.0.len();
+        let skip = skip_face.map(|f| self.split_face(f));
+
+        // The object's BVH works in object space, so distances are object-space too
+        let (_, (i, hit)) = self.object.bvh.intersect_nearest(&ray_obj, f64::INFINITY, |i, _| {
+            let shape = &self.object.shapes.0[i];
+
+            let hit = match skip {
+                Some((skip_shape, skip_face)) if skip_shape == i => shape.intersect_from_face(&ray_obj, skip_face),
+                _ => shape.intersect(&ray_obj),
+            };
+
+            hit.map(|h| ((h.coords - &ray_obj.position).len(), (i, h)))
+        })?;
+
+        let normal = self.transform.mat_invtf_norm_vec3(&hit.normal);
+
+        Some(IntersectData {
+            index: 0,
+            time: 0.0,
+            face: hit.face * count + i,
+            coords: self.transform * &hit.coords,
+            normal,
+            geom_normal: self.transform.mat_invtf_norm_vec3(&hit.geom_normal),
+            uv: hit.uv,
+            uv_density: hit.uv_density / self.transform.determinant3().abs().cbrt(),
+            material: if self.override_material { self.material } else { hit.material },
+        })
+    }
+}
+
+impl Shape for Instance {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        self.intersect_shapes(ray, None)
+    }
+
+    // Shapes in the object need not be convex, and rays leaving one can hit others
+    fn intersect_from_face(&self, ray: &Ray, face: usize) -> Option <IntersectData> {
+        self.intersect_shapes(ray, Some(face))
+    }
+
+    fn normal_at(&self, point: &Point3, face: usize) -> Vector3 {
+        let (i, shape_face) = self.split_face(face);
+        let obj_normal = self.object.shapes.0[i].normal_at(&self.transform.mat_invtf_point3(point), shape_face);
+
+        self.transform.mat_invtf_norm_vec3(&obj_normal)
+    }
+
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        let (i, shape_face) = self.split_face(face);
+
+        self.object.shapes.0[i].uv_at(&self.transform.mat_invtf_point3(point), shape_face)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let bounds = self.object.bounds();
+
+        if bounds.is_empty() || bounds.is_finite() { bounds.transform(&self.transform) } else { BoundingBox::new_infinite() }
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = self.transform.determinant3().abs().cbrt();
+
+        self.object.shapes.0.iter().map(|s| s.area()).sum::<f64>() * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let (i, u_shape) = self.object.pick_shape(u.0);
+        let (point, normal) = self.object.shapes.0[i].sample((u_shape, u.1));
+
+        (self.transform * &point, self.transform.mat_invtf_norm_vec3(&normal))
+    }
+
+    fn material(&self) -> &Material {
+        match self.object.shapes.0.first() {
+            Some(s) if !self.override_material => s.material(),
+            _ => &self.material,
+        }
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Instance(self))
+    }
+}
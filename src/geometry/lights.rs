@@ -1,9 +1,12 @@
+use std::f64::consts::PI;
+
 use crate::primitives::*;
 use super::*;
 
 pub enum LightType {
     Directional(DirectionalLight),
     Point(PointLight),
+    Area(AreaLight),
 }
 
 pub struct DirectionalLight {
@@ -16,6 +19,33 @@ pub struct PointLight {
     pub color: RGBColor,
 }
 
+// Rectangles and disks emit from their front side only, facing along edge_u x edge_v
+// and normal respectively
+#[derive(Debug, Clone, Copy)]
+pub enum AreaLightShape {
+    Rectangle { corner: Point3, edge_u: Vector3, edge_v: Vector3 },
+    Disk { center: Point3, normal: Vector3, radius: f64 },
+    Sphere { center: Point3, radius: f64 },
+}
+
+// Light emitted evenly from a surface. Unlike point lights it falls off with the
+// square of the distance, ignoring the scene's attenuation.
+pub struct AreaLight {
+    pub shape: AreaLightShape,
+    // Emitted radiance
+    pub color: RGBColor,
+    // Shadow rays traced per shaded point
+    pub samples: usize,
+}
+
+// Point on a light, weighted by the inverse of its probability density in solid angle
+// as seen from the shaded point. The weight is 0 for points facing away.
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    pub position: Point3,
+    pub weight: f64,
+}
+
 pub struct LightStack {
     pub attenuation: [f64; 3],
     pub lights: Vec <LightType>,
@@ -29,3 +59,157 @@ impl LightStack {
         }
     }
 }
+
+impl Default for LightStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Ray parameter where the ray hits the front of the plane through origin with the given
+// unit normal
+fn intersect_plane_front(ray: &Ray, origin: &Point3, normal: &Vector3) -> Option <f64> {
+    let divisor = ray.direction.dot(normal);
+
+    if divisor >= 0.0 {
+        return None;
+    }
+
+    let t = (*origin - &ray.position).dot(normal) / divisor;
+
+    if t > 0.0 { Some(t) } else { None }
+}
+
+impl AreaLightShape {
+    pub fn area(&self) -> f64 {
+        match *self {
+            AreaLightShape::Rectangle { edge_u, edge_v, .. } => edge_u.cross(&edge_v).len(),
+            AreaLightShape::Disk { radius, .. } => PI * radius * radius,
+            AreaLightShape::Sphere { radius, .. } => 4.0 * PI * radius * radius,
+        }
+    }
+
+    // Sample for the point from, placed by u in the unit square
+    pub fn sample(&self, from: &Point3, u: (f64, f64)) -> LightSample {
+        // Converts an area density sample to solid angle
+        let area_sample = |position: Point3, normal: Vector3, area: f64| {
+            let to_light = position - from;
+            let dist_sq = to_light.dot(&to_light);
+            let cos_light = -to_light.norm().dot(&normal);
+
+            LightSample {
+                position,
+                weight: if cos_light > 0.0 && dist_sq > 0.0 { cos_light * area / dist_sq } else { 0.0 },
+            }
+        };
+
+        match *self {
+            AreaLightShape::Rectangle { corner, edge_u, edge_v } => {
+                let position = corner + &(edge_u * u.0) + &(edge_v * u.1);
+
+                area_sample(position, edge_u.cross(&edge_v).norm(), self.area())
+            },
+            AreaLightShape::Disk { center, normal, radius } => {
+                let normal = normal.norm();
+                let (t, b) = normal.orthonormal_basis();
+                let (r, phi) = (radius * u.0.sqrt(), 2.0 * PI * u.1);
+                let position = center + &(t * (r * phi.cos())) + &(b * (r * phi.sin()));
+
+                area_sample(position, normal, self.area())
+            },
+            AreaLightShape::Sphere { center, radius } => {
+                let to_center = center - from;
+                let dist = to_center.len();
+
+                // Points inside the sphere see all of it
+                if dist <= radius {
+                    let z = 1.0 - 2.0 * u.0;
+                    let r = (1.0 - z * z).max(0.0).sqrt();
+                    let dir = Vector3::new(r * (2.0 * PI * u.1).cos(), r * (2.0 * PI * u.1).sin(), z);
+                    let position = center + &(dir * radius);
+
+                    return area_sample(position, dir * -1.0, self.area());
+                }
+
+                // Uniform over the cone of directions the sphere covers
+                let axis = to_center / dist;
+                let sin2_max = (radius * radius) / (dist * dist);
+                let cos_max = (1.0 - sin2_max).max(0.0).sqrt();
+                let cos_theta = 1.0 - u.0 * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u.1;
+
+                let (t, b) = axis.orthonormal_basis();
+                let dir = t * (sin_theta * phi.cos()) + &(b * (sin_theta * phi.sin())) + &(axis * cos_theta);
+                let hit_dist = dist * cos_theta - (radius * radius - dist * dist * sin_theta * sin_theta).max(0.0).sqrt();
+
+                LightSample {
+                    position: *from + &(dir * hit_dist),
+                    weight: 2.0 * PI * (1.0 - cos_max),
+                }
+            },
+        }
+    }
+
+    // Distance along the ray to the emitting side of the shape
+    pub fn intersect(&self, ray: &Ray) -> Option <f64> {
+        let ray = Ray {
+            position: ray.position,
+            direction: ray.direction.norm(),
+            time: ray.time,
+        };
+
+        match *self {
+            AreaLightShape::Rectangle { corner, edge_u, edge_v } => {
+                let cross = edge_u.cross(&edge_v);
+                let t = intersect_plane_front(&ray, &corner, &cross.norm())?;
+                let offset = (ray.position + &(ray.direction * t)) - &corner;
+
+                // Coordinates of the hit along both edges
+                let a = offset.cross(&edge_v).dot(&cross) / cross.dot(&cross);
+                let b = edge_u.cross(&offset).dot(&cross) / cross.dot(&cross);
+
+                if (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b) { Some(t) } else { None }
+            },
+            AreaLightShape::Disk { center, normal, radius } => {
+                let t = intersect_plane_front(&ray, &center, &normal.norm())?;
+                let offset = (ray.position + &(ray.direction * t)) - &center;
+
+                if offset.dot(&offset) <= radius * radius { Some(t) } else { None }
+            },
+            AreaLightShape::Sphere { center, radius } => {
+                let center_pos = ray.position - &center;
+                let b = ray.direction.dot(&center_pos);
+                let discriminant = b * b - (center_pos.dot(&center_pos) - radius * radius);
+
+                if discriminant < 0.0 {
+                    return None;
+                }
+
+                [-b - discriminant.sqrt(), -b + discriminant.sqrt()].into_iter().find(|t| *t > 0.0)
+            },
+        }
+    }
+}
+
+impl AreaLight {
+    pub fn new(shape: AreaLightShape, color: RGBColor) -> Self {
+        Self {
+            shape,
+            color,
+            samples: 16,
+        }
+    }
+}
+
+impl LightStack {
+    // Nearest area light hit by the ray, with its distance and emitted radiance
+    pub fn intersect_emitters(&self, ray: &Ray) -> Option <(f64, RGBColor)> {
+        self.lights.iter()
+            .filter_map(|l| match l {
+                LightType::Area(a) => a.shape.intersect(ray).map(|t| (t, a.color)),
+                _ => None,
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+}
@@ -1,7 +1,33 @@
 use crate::primitives::*;
+use super::texture::Texture;
 
 pub type RGBColor = Vector3;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialChannel {
+    Ambient,
+    Diffuse,
+    Specular,
+    Emission,
+}
+
+// Approximation used for the share of light reflected by a dielectric surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FresnelModel {
+    Schlick,
+    // Fresnel equations for unpolarized light
+    Exact,
+}
+
+// Indices into Scene::textures of the texture bound to each channel, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialTextures {
+    pub ambient: Option <usize>,
+    pub diffuse: Option <usize>,
+    pub specular: Option <usize>,
+    pub emission: Option <usize>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Material {
     pub ambient: RGBColor,
@@ -10,6 +36,124 @@ pub struct Material {
     pub emission: RGBColor,
 
     pub shininess: f64,
+
+    // Share of light passing through the surface. Transmissive materials split their
+    // mirror term between reflection and refraction by Fresnel weighting, in place of
+    // the specular-scaled reflection used by opaque ones.
+    pub transmission: RGBColor,
+    pub ior: f64,
+    // Beer-Lambert absorption coefficient per unit distance travelled inside
+    pub absorption: RGBColor,
+    pub fresnel: FresnelModel,
+
+    // Textured channels multiply their constant color by the texture's color
+    pub textures: MaterialTextures,
+}
+
+impl MaterialChannel {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "ambient" => Some(MaterialChannel::Ambient),
+            "diffuse" => Some(MaterialChannel::Diffuse),
+            "specular" => Some(MaterialChannel::Specular),
+            "emission" => Some(MaterialChannel::Emission),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaterialChannel::Ambient => "ambient",
+            MaterialChannel::Diffuse => "diffuse",
+            MaterialChannel::Specular => "specular",
+            MaterialChannel::Emission => "emission",
+        }
+    }
+}
+
+impl FresnelModel {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "schlick" => Some(FresnelModel::Schlick),
+            "exact" => Some(FresnelModel::Exact),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FresnelModel::Schlick => "schlick",
+            FresnelModel::Exact => "exact",
+        }
+    }
+
+    // Reflected share of light arriving at cos_i to the normal, going from a medium
+    // with index n1 into one with index n2
+    pub fn reflectance(&self, cos_i: f64, n1: f64, n2: f64) -> f64 {
+        let sin2_t = (n1 / n2) * (n1 / n2) * (1.0 - cos_i * cos_i);
+
+        // Total internal reflection
+        if sin2_t >= 1.0 {
+            return 1.0;
+        }
+
+        let cos_t = (1.0 - sin2_t).sqrt();
+
+        match self {
+            FresnelModel::Schlick => {
+                let r0 = ((n1 - n2) / (n1 + n2)) * ((n1 - n2) / (n1 + n2));
+                // The angle on the optically thinner side is the one to use
+                let cos = if n1 > n2 { cos_t } else { cos_i };
+
+                r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+            },
+            FresnelModel::Exact => {
+                let r_s = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+                let r_p = (n2 * cos_i - n1 * cos_t) / (n2 * cos_i + n1 * cos_t);
+
+                0.5 * (r_s * r_s + r_p * r_p)
+            },
+        }
+    }
+}
+
+impl MaterialTextures {
+    pub fn new() -> Self {
+        Self {
+            ambient: None,
+            diffuse: None,
+            specular: None,
+            emission: None,
+        }
+    }
+
+    pub fn get(&self, channel: MaterialChannel) -> Option <usize> {
+        match channel {
+            MaterialChannel::Ambient => self.ambient,
+            MaterialChannel::Diffuse => self.diffuse,
+            MaterialChannel::Specular => self.specular,
+            MaterialChannel::Emission => self.emission,
+        }
+    }
+
+    pub fn get_mut(&mut self, channel: MaterialChannel) -> &mut Option <usize> {
+        match channel {
+            MaterialChannel::Ambient => &mut self.ambient,
+            MaterialChannel::Diffuse => &mut self.diffuse,
+            MaterialChannel::Specular => &mut self.specular,
+            MaterialChannel::Emission => &mut self.emission,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ambient.is_none() && self.diffuse.is_none() && self.specular.is_none() && self.emission.is_none()
+    }
+}
+
+impl Default for MaterialTextures {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Material {
@@ -20,6 +164,33 @@ impl Material {
             specular: Vector3::new_empty(),
             emission: Vector3::new_empty(),
             shininess: 0.0,
+            transmission: Vector3::new_empty(),
+            ior: 1.5,
+            absorption: Vector3::new_empty(),
+            fresnel: FresnelModel::Exact,
+            textures: MaterialTextures::new(),
+        }
+    }
+
+    pub fn is_transmissive(&self) -> bool {
+        self.transmission[0] > 0.0 || self.transmission[1] > 0.0 || self.transmission[2] > 0.0
+    }
+
+    // Material with the textured channels evaluated at the given UV coordinates. The
+    // result has no textures left.
+    pub fn apply_textures(&self, textures: &[Texture], uv: (f64, f64), footprint: f64) -> Material {
+        let sample = |color: RGBColor, texture: Option <usize>| match texture {
+            Some(t) => color * &textures[t].sample(uv, footprint),
+            None => color,
+        };
+
+        Material {
+            ambient: sample(self.ambient, self.textures.ambient),
+            diffuse: sample(self.diffuse, self.textures.diffuse),
+            specular: sample(self.specular, self.textures.specular),
+            emission: sample(self.emission, self.textures.emission),
+            textures: MaterialTextures::new(),
+            ..*self
         }
     }
 }
\ No newline at end of file
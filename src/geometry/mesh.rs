@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use crate::primitives::*;
+use crate::raytracer::Bvh;
+use super::material::Material;
+
+// Indices of one triangle's corners into the mesh's attribute lists
+#[derive(Debug, Clone, Copy)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option <[usize; 3]>,
+    pub uvs: Option <[usize; 3]>,
+}
+
+// Indexed triangle geometry in object space, with its own acceleration structure.
+// Shared between all shapes placing it in the scene.
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    pub positions: Vec <Point3>,
+    pub normals: Vec <Vector3>,
+    pub uvs: Vec <(f64, f64)>,
+    pub faces: Vec <MeshFace>,
+
+    bvh: Bvh,
+    bounds: BoundingBox,
+    // Running total of face areas, for picking faces in proportion to their area
+    area_cdf: Vec <f64>,
+}
+
+// A mesh placed in the scene with a transform and material
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub mesh: Arc <TriangleMesh>,
+    pub transform: Matrix4,
+    pub material: Material,
+    // Mesh file as named in the scene, None for meshes made in code
+    pub source: Option <String>,
+}
+
+impl MeshFace {
+    pub fn new(vertices: [usize; 3]) -> Self {
+        Self {
+            vertices,
+            normals: None,
+            uvs: None,
+        }
+    }
+}
+
+impl TriangleMesh {
+    // Face indices must be in range of the attribute lists
+    pub fn new(positions: Vec <Point3>, normals: Vec <Vector3>, uvs: Vec <(f64, f64)>, faces: Vec <MeshFace>) -> Self {
+        let mut mesh = Self {
+            positions,
+            normals,
+            uvs,
+            faces,
+            bvh: Bvh::new(),
+            bounds: BoundingBox::new_empty(),
+            area_cdf: Vec::new(),
+        };
+
+        let face_bounds: Vec <BoundingBox> = (0..mesh.faces.len()).map(|f| BoundingBox::from_points(&mesh.face_vertices(f))).collect();
+        mesh.bvh = Bvh::build(&face_bounds);
+        mesh.bounds = mesh.bvh.bounds();
+
+        let mut area_sum = 0.0;
+        mesh.area_cdf = (0..mesh.faces.len()).map(|f| {
+            let [a, b, c] = mesh.face_vertices(f);
+            area_sum += 0.5 * (b - &a).cross(&(c - &a)).len();
+            area_sum
+        }).collect();
+
+        mesh
+    }
+
+    pub fn face_vertices(&self, face: usize) -> [Point3; 3] {
+        self.faces[face].vertices.map(|v| self.positions[v])
+    }
+
+    pub fn face_normals(&self, face: usize) -> Option <[Vector3; 3]> {
+        self.faces[face].normals.map(|n| n.map(|i| self.normals[i]))
+    }
+
+    pub fn face_uvs(&self, face: usize) -> Option <[(f64, f64); 3]> {
+        self.faces[face].uvs.map(|t| t.map(|i| self.uvs[i]))
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    // Object-space bounds
+    pub fn bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    // Face picked with probability proportional to its object-space area, along with u
+    // rescaled to [0, 1) within that face's share of the range
+    pub fn pick_face(&self, u: f64) -> (usize, f64) {
+        let total = match self.area_cdf.last() {
+            Some(t) if *t > 0.0 => *t,
+            _ => return (0, u),
+        };
+
+        let target = u * total;
+        let face = self.area_cdf.partition_point(|a| *a <= target).min(self.area_cdf.len() - 1);
+        let start = if face > 0 { self.area_cdf[face - 1] } else { 0.0 };
+        let face_area = self.area_cdf[face] - start;
+
+        (face, if face_area > 0.0 { ((target - start) / face_area).clamp(0.0, 1.0) } else { 0.0 })
+    }
+}
+
+impl Mesh {
+    pub fn new(mesh: Arc <TriangleMesh>) -> Self {
+        Self {
+            mesh,
+            transform: Matrix4::new_on_diag(1.0),
+            material: Material::new(),
+            source: None,
+        }
+    }
+}
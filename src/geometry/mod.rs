@@ -1,7 +1,19 @@
 mod shapes;
 mod material;
 mod lights;
+mod mesh;
+mod texture;
+mod analytic;
+mod csg;
+mod instance;
+mod moving;
 
 pub use material::*;
 pub use shapes::*;
-pub use lights::*;
\ No newline at end of file
+pub use lights::*;
+pub use mesh::*;
+pub use texture::*;
+pub use analytic::*;
+pub use csg::*;
+pub use instance::*;
+pub use moving::*;
\ No newline at end of file
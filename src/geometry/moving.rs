@@ -0,0 +1,112 @@
+use crate::primitives::*;
+use crate::raytracer::IntersectData;
+use super::material::Material;
+use super::shapes::{Shape, ShapeDescription, SolidSpan, SurfaceCrossing};
+
+// Shape following a transform that changes over time, placed wherever it is at the
+// time of each ray. The shape's own transform places it relative to the motion.
+// Queries without a time, such as sampling points on it, use where it is at the first
+// keyframe.
+#[derive(Debug)]
+pub struct Moving {
+    pub shape: Box <dyn Shape>,
+    pub motion: MotionTransform,
+}
+
+// World-space hit data for a hit on the shape placed with the given transform
+fn world_hit(transform: &Matrix4, hit: IntersectData) -> IntersectData {
+    IntersectData {
+        coords: *transform * &hit.coords,
+        normal: transform.mat_invtf_norm_vec3(&hit.normal),
+        geom_normal: transform.mat_invtf_norm_vec3(&hit.geom_normal),
+        uv_density: hit.uv_density / transform.determinant3().abs().cbrt(),
+        ..hit
+    }
+}
+
+impl Moving {
+    pub fn new<S: Shape + 'static>(shape: S, motion: MotionTransform) -> Self {
+        Self {
+            shape: Box::new(shape),
+            motion,
+        }
+    }
+
+    fn start_transform(&self) -> Matrix4 {
+        self.motion.keyframes()[0].transform
+    }
+}
+
+impl Shape for Moving {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let transform = self.motion.at(ray.time);
+
+        self.shape.intersect(&transform.mat_invtf_ray(ray)).map(|h| world_hit(&transform, h))
+    }
+
+    fn intersect_from_face(&self, ray: &Ray, face: usize) -> Option <IntersectData> {
+        let transform = self.motion.at(ray.time);
+
+        self.shape.intersect_from_face(&transform.mat_invtf_ray(ray), face).map(|h| world_hit(&transform, h))
+    }
+
+    fn normal_at(&self, point: &Point3, face: usize) -> Vector3 {
+        let transform = self.start_transform();
+
+        transform.mat_invtf_norm_vec3(&self.shape.normal_at(&transform.mat_invtf_point3(point), face))
+    }
+
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        self.shape.uv_at(&self.start_transform().mat_invtf_point3(point), face)
+    }
+
+    // Everywhere the shape goes while it moves
+    fn bounds(&self) -> BoundingBox {
+        self.motion.bounds(&self.shape.bounds())
+    }
+
+    fn bounds_between(&self, start: f64, end: f64) -> BoundingBox {
+        self.motion.bounds_between(&self.shape.bounds(), start, end)
+    }
+
+    fn moves(&self) -> bool {
+        true
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        let scale = self.start_transform().determinant3().abs().cbrt();
+
+        self.shape.area() * scale * scale
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let transform = self.start_transform();
+        let (point, normal) = self.shape.sample(u);
+
+        (transform * &point, transform.mat_invtf_norm_vec3(&normal))
+    }
+
+    fn material(&self) -> &Material {
+        self.shape.material()
+    }
+
+    fn is_solid(&self) -> bool {
+        self.shape.is_solid()
+    }
+
+    // Ray parameters are the same in both spaces, since the ray's direction is
+    // transformed without normalizing it
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let transform = self.motion.at(ray.time);
+        let to_world = |c: SurfaceCrossing| if c.t.is_finite() { SurfaceCrossing { t: c.t, hit: world_hit(&transform, c.hit) } } else { c };
+
+        self.shape.solid_spans(&transform.mat_invtf_ray(ray)).into_iter()
+            .map(|s| SolidSpan { entry: to_world(s.entry), exit: to_world(s.exit) })
+            .collect()
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Moving(self))
+    }
+}
@@ -1,16 +1,115 @@
+use std::fmt;
+
 use crate::primitives::*;
+use crate::raytracer::IntersectData;
 use super::material::Material;
+use super::analytic::{Plane, Cuboid, Cylinder, Cone, Disk, Torus};
+use super::mesh::Mesh;
+use super::csg::Csg;
+use super::instance::Instance;
+use super::moving::Moving;
+
+// Surface that can be placed in a scene. Besides the built-in primitives, other crates
+// can implement it for their own and add them with Shapes::push.
+pub trait Shape: fmt::Debug + Send + Sync {
+    // Nearest hit along the ray, in world space
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData>;
+
+    // Intersection for rays leaving the given face of this shape, which must not hit
+    // that face again. Shapes made of a single convex surface cannot be hit by such
+    // rays at all.
+    fn intersect_from_face(&self, _ray: &Ray, _face: usize) -> Option <IntersectData> {
+        None
+    }
+
+    // World-space unit normal of the actual surface at a point on the given face
+    fn normal_at(&self, point: &Point3, face: usize) -> Vector3;
+
+    // Texture coordinates at a point on the given face
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64);
+
+    // World-space bounds, taking the shape's transform into account
+    fn bounds(&self) -> BoundingBox;
+
+    // World-space bounds of everywhere the shape is between two times, only narrower
+    // than bounds for shapes that move
+    fn bounds_between(&self, _start: f64, _end: f64) -> BoundingBox {
+        self.bounds()
+    }
+
+    // Whether bounds_between depends on the times given
+    fn moves(&self) -> bool {
+        false
+    }
+
+    // World-space surface area
+    fn area(&self) -> f64;
+
+    // Point on the surface with its normal, spread evenly over the area as u covers
+    // the unit square
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3);
+
+    fn material(&self) -> &Material;
 
+    // Whether the shape encloses a volume described by solid_spans, so that it can be
+    // combined with others in a Csg
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    // Stretches of the ray's line inside the shape, in order along it. Unlike
+    // intersect, spans behind the ray's origin are included too.
+    fn solid_spans(&self, _ray: &Ray) -> Vec <SolidSpan> {
+        Vec::new()
+    }
+
+    // Which of the built-in shapes this is, None for shapes from other crates
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        None
+    }
+}
+
+// Built-in shape behind a Shape, giving code such as the scene writer access to its
+// parameters
 #[derive(Debug, Clone, Copy)]
-pub enum ShapeType {
-    Triangle(Triangle),
-    Sphere(Sphere),
-    None,
+pub enum ShapeDescription<'a> {
+    Triangle(&'a Triangle),
+    Sphere(&'a Sphere),
+    Plane(&'a Plane),
+    Box(&'a Cuboid),
+    Cylinder(&'a Cylinder),
+    Cone(&'a Cone),
+    Disk(&'a Disk),
+    Torus(&'a Torus),
+    Mesh(&'a Mesh),
+    Csg(&'a Csg),
+    Instance(&'a Instance),
+    Moving(&'a Moving),
+}
+
+// Point where a ray crosses the surface of a solid, at position + direction * t. The
+// hit's normal points out of the solid.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceCrossing {
+    pub t: f64,
+    pub hit: IntersectData,
+}
+
+// Stretch of a ray inside a solid. Solids without bounds have spans starting or ending
+// at an infinite t, where the hit data is meaningless.
+#[derive(Debug, Clone, Copy)]
+pub struct SolidSpan {
+    pub entry: SurfaceCrossing,
+    pub exit: SurfaceCrossing,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Triangle {
     pub vertices: [Point3; 3],
+    // Object-space normals at each vertex, for smooth shading
+    pub normals: Option <[Vector3; 3]>,
+    // Texture coordinates at each vertex
+    pub uvs: Option <[(f64, f64); 3]>,
     pub transform: Matrix4,
     pub material: Material,
 }
@@ -26,8 +125,16 @@ pub struct Sphere {
 #[derive(Debug, Clone)]
 pub struct VertexStack(pub Vec <Point3>);
 
+// Vertices defined together with a normal, used by trinormal
+#[derive(Debug, Clone)]
+pub struct VertexNormalStack(pub Vec <(Point3, Vector3)>);
+
+// Vertices defined together with texture coordinates, used by tritex
 #[derive(Debug, Clone)]
-pub struct Shapes(pub Vec <ShapeType>);
+pub struct VertexTexStack(pub Vec <(Point3, (f64, f64))>);
+
+#[derive(Debug)]
+pub struct Shapes(pub Vec <Box <dyn Shape>>);
 
 impl VertexStack {
     pub fn new() -> Self {
@@ -35,10 +142,38 @@ impl VertexStack {
     }
 }
 
+impl VertexNormalStack {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl Default for VertexNormalStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VertexTexStack {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl Default for VertexTexStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Shapes {
     pub fn new() -> Self {
         Self(Vec::new())
     }
+
+    pub fn push<S: Shape + 'static>(&mut self, shape: S) {
+        self.0.push(Box::new(shape));
+    }
 }
 
 impl Sphere {
@@ -56,8 +191,35 @@ impl Triangle {
     pub fn new() -> Self {
         Self {
             vertices: [Point3::new_empty(), Point3::new_empty(), Point3::new_empty()],
+            normals: None,
+            uvs: None,
             transform: Matrix4::new_on_diag(1.0),
             material: Material::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+impl SolidSpan {
+    // Pairs up crossings of a closed surface, sorted along the ray, into spans. Each
+    // crossing the ray enters through is matched with the next one it leaves through,
+    // which skips over doubled crossings where the ray grazes the surface.
+    pub fn from_crossings(ray: &Ray, crossings: &[SurfaceCrossing]) -> Vec <SolidSpan> {
+        let mut spans: Vec <SolidSpan> = Vec::new();
+        let mut entry: Option <SurfaceCrossing> = None;
+
+        for crossing in crossings {
+            let entering = ray.direction.dot(&crossing.hit.geom_normal) < 0.0;
+
+            match (entering, entry) {
+                (true, None) => entry = Some(*crossing),
+                (false, Some(e)) => {
+                    spans.push(SolidSpan { entry: e, exit: *crossing });
+                    entry = None;
+                },
+                _ => (),
+            }
+        }
+
+        spans
+    }
+}
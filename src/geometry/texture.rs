@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+use image::DynamicImage;
+
+use crate::primitives::*;
+use super::material::RGBColor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+    // Bilinear on the two mip levels closest to the footprint, blended together
+    Trilinear,
+}
+
+// How UV coordinates outside [0, 1] are mapped back onto the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec <RGBColor>,
+}
+
+// An image along with successively halved copies of it, down to a single texel
+#[derive(Debug, Clone)]
+pub struct MipMap {
+    levels: Vec <MipLevel>,
+}
+
+// An image bound to a material channel. The image is shared between all textures
+// using the same file.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    pub image: Arc <MipMap>,
+    pub filter: TextureFilter,
+    pub wrap: WrapMode,
+    // Image file as named in the scene, None for images made in code
+    pub source: Option <String>,
+}
+
+impl TextureFilter {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "nearest" => Some(TextureFilter::Nearest),
+            "bilinear" => Some(TextureFilter::Bilinear),
+            "trilinear" => Some(TextureFilter::Trilinear),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureFilter::Nearest => "nearest",
+            TextureFilter::Bilinear => "bilinear",
+            TextureFilter::Trilinear => "trilinear",
+        }
+    }
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WrapMode::Repeat => "repeat",
+            WrapMode::Clamp => "clamp",
+            WrapMode::Mirror => "mirror",
+        }
+    }
+
+    // Texel index inside [0, size) for a possibly out of range index
+    fn apply(&self, index: isize, size: usize) -> usize {
+        let size = size as isize;
+
+        let wrapped = match self {
+            WrapMode::Repeat => index.rem_euclid(size),
+            WrapMode::Clamp => index.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let m = index.rem_euclid(2 * size);
+
+                if m < size { m } else { 2 * size - 1 - m }
+            },
+        };
+
+        wrapped as usize
+    }
+}
+
+impl MipLevel {
+    fn texel(&self, x: isize, y: isize, wrap: WrapMode) -> RGBColor {
+        self.texels[wrap.apply(y, self.height) * self.width + wrap.apply(x, self.width)]
+    }
+
+    // Box filtered copy at half the resolution, rounding odd sizes down
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels: Vec <RGBColor> = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = ((2 * x) as isize, (2 * y) as isize);
+                let sum = self.texel(x0, y0, WrapMode::Clamp)
+                    + &self.texel(x0 + 1, y0, WrapMode::Clamp)
+                    + &self.texel(x0, y0 + 1, WrapMode::Clamp)
+                    + &self.texel(x0 + 1, y0 + 1, WrapMode::Clamp);
+
+                texels.push(sum * 0.25);
+            }
+        }
+
+        Self { width, height, texels }
+    }
+
+    fn sample_nearest(&self, u: f64, v: f64, wrap: WrapMode) -> RGBColor {
+        let x = (u * (self.width as f64)).floor() as isize;
+        let y = ((1.0 - v) * (self.height as f64)).floor() as isize;
+
+        self.texel(x, y, wrap)
+    }
+
+    fn sample_bilinear(&self, u: f64, v: f64, wrap: WrapMode) -> RGBColor {
+        // Texel centers sit at half-integer coordinates
+        let x = u * (self.width as f64) - 0.5;
+        let y = (1.0 - v) * (self.height as f64) - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let top = self.texel(x0, y0, wrap) * (1.0 - fx) + &(self.texel(x0 + 1, y0, wrap) * fx);
+        let bottom = self.texel(x0, y0 + 1, wrap) * (1.0 - fx) + &(self.texel(x0 + 1, y0 + 1, wrap) * fx);
+
+        top * (1.0 - fy) + &(bottom * fy)
+    }
+}
+
+impl MipMap {
+    // Texel values are taken as they are stored, without any gamma decoding
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let rgb = image.to_rgb32f();
+        let base = MipLevel {
+            width: (rgb.width() as usize).max(1),
+            height: (rgb.height() as usize).max(1),
+            texels: if rgb.width() == 0 || rgb.height() == 0 {
+                vec![RGBColor::new_empty()]
+            }
+            else {
+                rgb.pixels().map(|p| Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64)).collect()
+            },
+        };
+
+        let mut levels = vec![base];
+
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+}
+
+impl Texture {
+    pub fn new(image: Arc <MipMap>) -> Self {
+        Self {
+            image,
+            filter: TextureFilter::Bilinear,
+            wrap: WrapMode::Repeat,
+            source: None,
+        }
+    }
+
+    // Color at the given UV coordinates, with (0, 0) at the bottom left of the image.
+    // footprint is the width in UV units of the area seen by one pixel, used to pick
+    // mip levels for trilinear filtering.
+    pub fn sample(&self, uv: (f64, f64), footprint: f64) -> RGBColor {
+        let (u, v) = uv;
+        let levels = &self.image.levels;
+
+        match self.filter {
+            TextureFilter::Nearest => levels[0].sample_nearest(u, v, self.wrap),
+            TextureFilter::Bilinear => levels[0].sample_bilinear(u, v, self.wrap),
+            TextureFilter::Trilinear => {
+                let texels_covered = footprint * (self.image.width().max(self.image.height()) as f64);
+                let max_level = (levels.len() - 1) as f64;
+
+                let lod = if texels_covered > 1.0 && texels_covered.is_finite() {
+                    texels_covered.log2().min(max_level)
+                }
+                else {
+                    0.0
+                };
+
+                let lower = lod.floor() as usize;
+                let blend = lod - (lower as f64);
+                let color = levels[lower].sample_bilinear(u, v, self.wrap);
+
+                if blend > 0.0 {
+                    color * (1.0 - blend) + &(levels[lower + 1].sample_bilinear(u, v, self.wrap) * blend)
+                }
+                else {
+                    color
+                }
+            },
+        }
+    }
+}
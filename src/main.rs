@@ -1,67 +1,162 @@
-mod primitives;
-mod geometry;
-mod raytracer;
+mod cli;
 
 use std::env;
-use primitives::{Vector3, Point3, Matrix4, Ray};
-use raytracer::{read_scene_file, write_image};
-
-fn test1() {
-    let mymat = Matrix4::new(
-        2.0, 2.0, 3.0, 3.0,
-        5.0, 6.0, 7.0, 7.0,
-        9.0, 10.0, 11.0, 11.0,
-        0.0, 0.0, 0.0, 1.0,
-    );
-    let pos = Point3::new(8.0, 4.0, -2.0);
-    let dir = Vector3::new(-3.0, -4.0, 7.0);
-    let myray = Ray { position: pos, direction: dir };
-
-    println!("Ray pos: {:?}", myray.position);
-    println!("Ray dir: {:?}", myray.direction);
-
-    println!("Transforming ray...");
-    let myray1 = mymat * &myray;
-    println!("Ray pos: {:?}", myray1.position);
-    println!("Ray dir: {:?}", myray1.direction);
-
-    println!("Inverse transforming ray...");
-    let myray2 = mymat.mat_invtf_ray(&myray);
-    println!("Ray pos: {:?}", myray2.position);
-    println!("Ray dir: {:?}", myray2.direction);
-
-    println!("Undo inverse transform on ray...");
-    let myray3 = mymat * &myray2;
-    println!("Ray pos: {:?}", myray3.position);
-    println!("Ray dir: {:?}", myray3.direction);
-}
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use cli::{CliOptions, Verbosity, USAGE, parse_args};
+use raytracer::raytracer::{read_scene_file_with_mode, parse_scene_with_mode, write_framebuffer, frame_file_name, render_with_options, Framebuffer, ParseMode, RenderOptions, ToneMapping};
+
+// A scene could not be read or its image could not be written
+const EXIT_FAILURE: u8 = 1;
+// The command line itself is wrong
+const EXIT_USAGE: u8 = 2;
 
-fn main() {
-    let args: Vec <String> = env::args().collect();
+fn save_image(file_path_str: &str, framebuffer: &Framebuffer, tone_mapping: &ToneMapping, options: &CliOptions) -> Result <(), String> {
+    if let Err(e) = write_framebuffer(file_path_str, framebuffer, tone_mapping) {
+        return Err(format!("cannot write image \"{}\": {}", file_path_str, e));
+    }
 
-    if args.len() < 2 {
-        panic!("No scene files to render!");
+    if options.verbosity != Verbosity::Quiet {
+        println!("Wrote \"{}\"", file_path_str);
     }
-    use std::time::Instant;
+
+    Ok(())
+}
+
+fn render_scene(scene_path: &str, options: &CliOptions) -> Result <(), String> {
     let now = Instant::now();
+    let verbose = options.verbosity == Verbosity::Verbose;
+
+    if verbose {
+        println!("Reading scene file \"{}\"...", scene_path);
+    }
+
+    let mode = if options.strict { ParseMode::Strict } else { ParseMode::Lenient };
+
+    // A scene named - is read from standard input
+    let parsed = if scene_path == "-" {
+        parse_scene_with_mode(io::stdin().lock(), Path::new(""), mode)
+    }
+    else {
+        read_scene_file_with_mode(scene_path, mode)
+    };
+
+    let mut scene = match parsed {
+        Ok((scene, warnings)) => {
+            if options.verbosity != Verbosity::Quiet {
+                for w in warnings {
+                    eprintln!("{}: warning: {}", scene_path, w);
+                }
+            }
+
+            scene
+        },
+        Err(e) => return Err(format!("cannot read scene file \"{}\": {}", scene_path, e)),
+    };
+
+    if let Some((width, height)) = options.resolution {
+        scene.img_width = width;
+        scene.img_height = height;
+    }
+
+    if let Some(depth) = options.max_depth {
+        scene.max_recurse_depth = depth;
+    }
+
+    if let Some(samples) = options.samples {
+        scene.sampling.samples_per_pixel = samples;
+    }
+
+    if let Some(operator) = options.tone_map {
+        scene.tone_mapping.operator = operator;
+    }
 
-    println!("Reading scene file \"{}\"...", args[1]);
-    let scene = read_scene_file(&args[1]);
-    println!("===== SCENE INFO =====");
-    println!("Image size: {}x{}", scene.img_width, scene.img_height);
-    println!("# vertices: {}", scene.vertices.0.len());
-    println!("# shapes: {}", scene.shapes.0.len());
-    println!("# lights: {}", scene.lights.lights.len());
+    if let Some(exposure) = options.exposure {
+        scene.tone_mapping.exposure = exposure;
+    }
+
+    if options.srgb {
+        scene.tone_mapping.srgb = true;
+    }
+
+    let frames = options.frames.or(scene.frame_range);
+
+    if verbose {
+        println!("===== SCENE INFO =====");
+        println!("Image size: {}x{}", scene.img_width, scene.img_height);
+        println!("# vertices: {}", scene.vertices.0.len());
+        println!("# shapes: {}", scene.shapes.0.len());
+        println!("# lights: {}", scene.lights.lights.len());
+
+        if let Some(range) = frames {
+            println!("# frames: {} ({} to {})", range.len(), range.first, range.last);
+        }
+
+        println!("Rendering scene. This will take some time...");
+    }
+
+    let render_options = RenderOptions {
+        num_threads: options.threads,
+        ..RenderOptions::new()
+    };
+    let output = options.output_path(scene_path, scene.output.as_deref());
+
+    match frames {
+        Some(range) => {
+            // Parsed shapes and their acceleration structures are reused for every frame
+            for frame in range.frames() {
+                scene.set_frame(frame);
+
+                let framebuffer = render_with_options(&scene, &render_options);
+                save_image(&frame_file_name(&output, frame), &framebuffer, &scene.tone_mapping, options)?;
+            }
+        },
+        None => {
+            let framebuffer = render_with_options(&scene, &render_options);
+            save_image(&output, &framebuffer, &scene.tone_mapping, options)?;
+        },
+    }
+
+    if verbose {
+        println!("Elapsed: {:.2?}", now.elapsed());
+    }
 
-    println!("Rendering scene. This will take some time...");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec <String> = env::args().skip(1).collect();
 
-    let pixels = raytracer::render(&scene);
-    let elapsed = now.elapsed();
+    let options = match parse_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("raytracer: {}\n\n{}", e, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        },
+    };
 
+    if options.help {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
 
-    println!("Saving scene to \"{}\"", "out.png");
-    let img_obj = raytracer::build_image((scene.img_width, scene.img_height), &pixels);
-    write_image(&"out.png".to_string(), img_obj);
-    println!("Elapsed: {:.2?}", elapsed);
-    println!("===== RENDERING DONE!!! =====");
+    // Later scenes are still rendered when one fails
+    let mut failed = false;
+
+    for scene_path in &options.scenes {
+        if let Err(e) = render_scene(scene_path, &options) {
+            eprintln!("raytracer: {}", e);
+            failed = true;
+        }
+    }
+
+    if failed {
+        ExitCode::from(EXIT_FAILURE)
+    }
+    else {
+        ExitCode::SUCCESS
+    }
 }
@@ -0,0 +1,140 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl BoundingBox {
+    pub fn new(min: Point3, max: Point3) -> Self {
+        Self {
+            min,
+            max,
+        }
+    }
+
+    // Inverted box, so that growing it by anything yields that thing
+    pub fn new_empty() -> Self {
+        Self::new(Point3::new_with_value(f64::INFINITY), Point3::new_with_value(f64::NEG_INFINITY))
+    }
+
+    // Box containing all of space, for shapes such as planes that extend forever
+    pub fn new_infinite() -> Self {
+        Self::new(Point3::new_with_value(f64::NEG_INFINITY), Point3::new_with_value(f64::INFINITY))
+    }
+
+    pub fn from_points(points: &[Point3]) -> Self {
+        points.iter().fold(Self::new_empty(), |acc, p| acc.grow(p))
+    }
+}
+
+impl BoundingBox {
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|i| self.min[i].is_finite() && self.max[i].is_finite())
+    }
+
+    pub fn grow(self, point: &Point3) -> BoundingBox {
+        let mut ans = self;
+
+        for i in 0..3 {
+            ans.min[i] = ans.min[i].min(point[i]);
+            ans.max[i] = ans.max[i].max(point[i]);
+        }
+
+        ans
+    }
+
+    pub fn union(self, other: &BoundingBox) -> BoundingBox {
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    pub fn extent(&self) -> Vector3 {
+        self.max - &self.min
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        self.min + &(self.extent() * 0.5)
+    }
+
+    pub fn largest_axis(&self) -> usize {
+        let ext = self.extent();
+
+        if ext[0] >= ext[1] && ext[0] >= ext[2] {
+            0
+        }
+        else if ext[1] >= ext[2] {
+            1
+        }
+        else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let ext = self.extent();
+
+        2.0 * (ext[0] * ext[1] + ext[1] * ext[2] + ext[2] * ext[0])
+    }
+
+    pub fn corners(&self) -> [Point3; 8] {
+        let mut ans = [Point3::new_empty(); 8];
+
+        for (i, corner) in ans.iter_mut().enumerate() {
+            for axis in 0..3 {
+                corner[axis] = if (i >> axis) & 1 == 0 { self.min[axis] } else { self.max[axis] };
+            }
+        }
+
+        ans
+    }
+
+    // World-space box of an object-space box, obtained by transforming all eight corners
+    pub fn transform(&self, transform: &Matrix4) -> BoundingBox {
+        if self.is_empty() {
+            return *self;
+        }
+
+        self.corners().iter().fold(BoundingBox::new_empty(), |acc, c| acc.grow(&(*transform * c)))
+    }
+
+    // Slab test. Returns the entry distance along the ray if it is below max_dist.
+    // inv_dir must be the componentwise reciprocal of the ray direction.
+    pub fn intersect_ray(&self, ray: &Ray, inv_dir: &Vector3, max_dist: f64) -> Option <f64> {
+        let mut t_near = 0.0_f64;
+        let mut t_far = max_dist;
+
+        for i in 0..3 {
+            let t0 = (self.min[i] - ray.position[i]) * inv_dir[i];
+            let t1 = (self.max[i] - ray.position[i]) * inv_dir[i];
+            let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+
+            // Pad the exit distance against rounding, flat boxes (e.g. around an
+            // axis-aligned triangle) would otherwise miss grazing rays
+            let t1 = t1 * (1.0 + 1e-9);
+
+            // NaN (0 * inf) compares false, leaving the interval untouched on that axis
+            if t0 > t_near {
+                t_near = t0;
+            }
+
+            if t1 < t_far {
+                t_far = t1;
+            }
+
+            if t_near > t_far {
+                return None;
+            }
+        }
+
+        Some(t_near)
+    }
+}
@@ -1,5 +1,24 @@
+use std::f64::consts::PI;
+
 use super::*;
 
+// How directions in the scene map onto the image
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Projection {
+    // fovy is the angle covered by the image height
+    Perspective,
+    // Parallel rays over a view of the given height in world units. A height of 0
+    // matches the perspective view's height at the center point.
+    Orthographic { height: f64 },
+    // Equidistant fisheye with fovy across the image height. The angle to the viewing
+    // direction grows linearly with the distance from the image center, and the image
+    // is black beyond fovy / 2.
+    Fisheye,
+    // Every direction around the eye, with longitude across the image width and
+    // latitude down its height
+    Equirectangular,
+}
+
 #[derive(Clone, Debug, Copy)]
 pub struct Camera {
     pub eye: Vector3,
@@ -7,6 +26,44 @@ pub struct Camera {
     pub up: Vector3,
 
     pub fovy: f64,
+    pub projection: Projection,
+
+    // Radius of the lens opening, 0 for a pinhole camera with everything in focus. Only
+    // perspective cameras have a lens.
+    pub aperture: f64,
+    // Distance from the eye to the plane in focus, 0 for the distance to center
+    pub focus_distance: f64,
+    // Number of straight aperture blades, giving polygonal bokeh. Below 3 the opening
+    // is round.
+    pub blades: usize,
+    // Rotation of the aperture polygon in degrees
+    pub blade_rotation: f64,
+
+    // Times the shutter opens and closes. Rays are spread evenly over the interval,
+    // blurring shapes that move during it.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl Projection {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic { height: 0.0 }),
+            "fisheye" => Some(Projection::Fisheye),
+            "equirectangular" => Some(Projection::Equirectangular),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic { .. } => "orthographic",
+            Projection::Fisheye => "fisheye",
+            Projection::Equirectangular => "equirectangular",
+        }
+    }
 }
 
 impl Camera {
@@ -16,6 +73,64 @@ impl Camera {
             center: Vector3::new_empty(),
             up: Vector3::new_empty(),
             fovy: 0.0,
+            projection: Projection::Perspective,
+            aperture: 0.0,
+            focus_distance: 0.0,
+            blades: 0,
+            blade_rotation: 0.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+
+    pub fn focus_distance(&self) -> f64 {
+        if self.focus_distance > 0.0 {
+            self.focus_distance
+        }
+        else {
+            (self.center - &self.eye).len()
         }
     }
+
+    pub fn has_motion_blur(&self) -> bool {
+        self.shutter_close > self.shutter_open
+    }
+
+    // Time within the shutter interval, spread evenly over it as u covers [0, 1)
+    pub fn shutter_time(&self, u: f64) -> f64 {
+        self.shutter_open + u * (self.shutter_close - self.shutter_open)
+    }
+
+    // Height of the orthographic view in world units
+    pub fn ortho_height(&self) -> f64 {
+        match self.projection {
+            Projection::Orthographic { height } if height > 0.0 => height,
+            _ => 2.0 * (0.5 * self.fovy.to_radians()).tan() * (self.center - &self.eye).len(),
+        }
+    }
+
+    // Point on the aperture, relative to its center in the camera's right and up
+    // directions, spread evenly over its area as u covers the unit square
+    pub fn sample_aperture(&self, u: (f64, f64)) -> (f64, f64) {
+        if self.blades < 3 {
+            let (r, phi) = (self.aperture * u.0.sqrt(), 2.0 * PI * u.1);
+
+            return (r * phi.cos(), r * phi.sin());
+        }
+
+        // Pick one of the equal triangles between the center and each polygon edge
+        let n = self.blades as f64;
+        let side = (u.0 * n).floor().min(n - 1.0);
+        let u_side = u.0 * n - side;
+
+        let corner = |i: f64| {
+            let angle = self.blade_rotation.to_radians() + 2.0 * PI * i / n;
+            (angle.cos(), angle.sin())
+        };
+
+        let (a, b) = (corner(side), corner(side + 1.0));
+        let s = self.aperture * u_side.sqrt();
+
+        (s * ((1.0 - u.1) * a.0 + u.1 * b.0), s * ((1.0 - u.1) * a.1 + u.1 * b.1))
+    }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use std::{ops};
 
 use super::*;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Matrix4 {
     mat: [[f64; 4]; 4],
 }
@@ -129,6 +129,7 @@ impl Matrix4 {
             let new_ray = Ray {
                 position: my_ray.position - &x3,
                 direction: my_ray.direction,
+                time: my_ray.time,
             };
 
             blockmat * &new_ray
@@ -176,6 +177,60 @@ impl Matrix4 {
 
         ans.norm()
     }
+
+    // Determinant of the upper left 3x3 block, i.e. the volume scale of the transform
+    pub fn determinant3(&self) -> f64 {
+        let x0 = Vector3::new(self[[0, 0]], self[[1, 0]], self[[2, 0]]);
+        let x1 = Vector3::new(self[[0, 1]], self[[1, 1]], self[[2, 1]]);
+        let x2 = Vector3::new(self[[0, 2]], self[[1, 2]], self[[2, 2]]);
+
+        x0.dot(&x1.cross(&x2))
+    }
+
+    // Transform that gives this one when applied after base, i.e. base * ans == self
+    pub fn relative_to(&self, base: &Matrix4) -> Matrix4 {
+        let column = |j: usize| base.mat_invtf_vec3(&Vector3::new(self[[0, j]], self[[1, j]], self[[2, j]]));
+        let offset = base.mat_invtf_point3(&Point3::new(self[[0, 3]], self[[1, 3]], self[[2, 3]]));
+
+        let mut ans = Matrix4::new_with_vec3(&column(0), &column(1), &column(2));
+
+        for i in 0..3 {
+            ans[[i, 3]] = offset[i];
+        }
+
+        ans
+    }
+
+    // Inverse transpose of the upper left 3x3 block, with the rest taken from the
+    // identity. Singular blocks are returned unchanged.
+    pub fn inverse_transpose3(&self) -> Matrix4 {
+        let x0 = Vector3::new(self[[0, 0]], self[[1, 0]], self[[2, 0]]);
+        let x1 = Vector3::new(self[[0, 1]], self[[1, 1]], self[[2, 1]]);
+        let x2 = Vector3::new(self[[0, 2]], self[[1, 2]], self[[2, 2]]);
+
+        let determ = x0.dot(&x1.cross(&x2));
+        let mut ans = Matrix4::new_on_diag(1.0);
+
+        if determ.abs() <= 1e-7 {
+            for i in 0..3 {
+                for j in 0..3 {
+                    ans[[i, j]] = self[[i, j]];
+                }
+            }
+
+            return ans;
+        }
+
+        let (y0, y1, y2) = (x1.cross(&x2), x2.cross(&x0), x0.cross(&x1));
+
+        for i in 0..3 {
+            ans[[i, 0]] = y0[i] / determ;
+            ans[[i, 1]] = y1[i] / determ;
+            ans[[i, 2]] = y2[i] / determ;
+        }
+
+        ans
+    }
 }
 
 impl ops::Add <&Matrix4> for Matrix4 {
@@ -230,6 +285,7 @@ impl ops::Mul <&Ray> for Matrix4 {
 
     fn mul(self, other: &Ray) -> Ray {
         let mut ans = Ray::new();
+        ans.time = other.time;
 
         let x4 = self[[3, 0]] * other.position[0]
             + self[[3, 1]] * other.position[1]
@@ -3,9 +3,15 @@ mod vector3;
 mod matrix4;
 mod camera;
 mod ray;
+mod bbox;
+mod quaternion;
+mod motion;
 
 pub use point3::*;
 pub use vector3::*;
 pub use matrix4::*;
 pub use camera::*;
-pub use ray::*;
\ No newline at end of file
+pub use ray::*;
+pub use bbox::*;
+pub use quaternion::*;
+pub use motion::*;
\ No newline at end of file
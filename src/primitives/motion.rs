@@ -0,0 +1,164 @@
+use super::*;
+
+// Iterations of the polar decomposition before settling for what it has
+const POLAR_ITERATIONS: usize = 100;
+
+// Steps each keyframe interval is split into when bounding the swept volume
+const BOUND_STEPS: usize = 32;
+
+// Transform split into translation * rotation * scale, so that each part can be
+// interpolated on its own. scale may also shear.
+#[derive(Debug, Clone, Copy)]
+struct Decomposed {
+    translation: Vector3,
+    rotation: Quaternion,
+    scale: Matrix4,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub transform: Matrix4,
+}
+
+// Transform that changes over time, passing through each keyframe at its time and
+// staying at the first and last one before and after them. In between translation and
+// scale change linearly and rotation turns at a constant rate.
+#[derive(Debug, Clone)]
+pub struct MotionTransform {
+    keyframes: Vec <Keyframe>,
+    decomposed: Vec <Decomposed>,
+}
+
+fn transpose3(m: &Matrix4) -> Matrix4 {
+    let mut ans = Matrix4::new_on_diag(1.0);
+
+    for i in 0..3 {
+        for j in 0..3 {
+            ans[[i, j]] = m[[j, i]];
+        }
+    }
+
+    ans
+}
+
+fn decompose(transform: &Matrix4) -> Decomposed {
+    let translation = Vector3::new(transform[[0, 3]], transform[[1, 3]], transform[[2, 3]]);
+
+    let mut linear = *transform;
+    for i in 0..3 {
+        linear[[i, 3]] = 0.0;
+        linear[[3, i]] = 0.0;
+    }
+    linear[[3, 3]] = 1.0;
+
+    // Polar decomposition: averaging with the inverse transpose converges to the
+    // closest orthonormal matrix
+    let mut rotation = linear;
+    for _ in 0..POLAR_ITERATIONS {
+        let next = (rotation + &rotation.inverse_transpose3()) * 0.5;
+        let change = (0..3).flat_map(|i| (0..3).map(move |j| (i, j)))
+            .map(|(i, j)| (next[[i, j]] - rotation[[i, j]]).abs())
+            .fold(0.0, f64::max);
+
+        rotation = next;
+        rotation[[3, 3]] = 1.0;
+
+        if change < 1e-12 {
+            break;
+        }
+    }
+
+    // Mirroring is left to the scale, quaternions only describe proper rotations
+    if rotation.determinant3() < 0.0 {
+        rotation = rotation * -1.0;
+        rotation[[3, 3]] = 1.0;
+    }
+
+    Decomposed {
+        translation,
+        rotation: Quaternion::from_matrix(&rotation),
+        scale: transpose3(&rotation) * &linear,
+    }
+}
+
+impl MotionTransform {
+    // Keyframes may be given in any order. There must be at least one.
+    pub fn new(keyframes: &[Keyframe]) -> Self {
+        let mut keyframes = keyframes.to_vec();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let decomposed = keyframes.iter().map(|k| decompose(&k.transform)).collect();
+
+        Self {
+            keyframes,
+            decomposed,
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn at(&self, time: f64) -> Matrix4 {
+        let last = self.keyframes.len() - 1;
+
+        if time <= self.keyframes[0].time {
+            return self.keyframes[0].transform;
+        }
+
+        if time >= self.keyframes[last].time {
+            return self.keyframes[last].transform;
+        }
+
+        let i = self.keyframes.partition_point(|k| k.time <= time) - 1;
+        let span = self.keyframes[i + 1].time - self.keyframes[i].time;
+        let t = (time - self.keyframes[i].time) / span;
+
+        let (a, b) = (&self.decomposed[i], &self.decomposed[i + 1]);
+        let translation = a.translation + &((b.translation - &a.translation) * t);
+        let rotation = a.rotation.slerp(&b.rotation, t);
+        let scale = a.scale * (1.0 - t) + &(b.scale * t);
+
+        Matrix4::new_translate(translation[0], translation[1], translation[2]) * &rotation.to_matrix() * &scale
+    }
+
+    // World-space box around everywhere an object-space box goes
+    pub fn bounds(&self, bounds: &BoundingBox) -> BoundingBox {
+        let last = self.keyframes.len() - 1;
+
+        self.bounds_between(bounds, self.keyframes[0].time, self.keyframes[last].time)
+    }
+
+    // World-space box around everywhere an object-space box goes between two times.
+    // Rotations can carry the corners slightly outside the steps sampled, so the box is
+    // padded for that.
+    pub fn bounds_between(&self, bounds: &BoundingBox, start: f64, end: f64) -> BoundingBox {
+        if bounds.is_empty() {
+            return *bounds;
+        }
+
+        if !bounds.is_finite() {
+            return BoundingBox::new_infinite();
+        }
+
+        // The motion only changes course at keyframes
+        let mut times = vec![start];
+        times.extend(self.keyframes.iter().map(|k| k.time).filter(|t| *t > start && *t < end));
+        times.push(end.max(start));
+
+        let mut swept = bounds.transform(&self.at(start));
+
+        for pair in times.windows(2) {
+            for step in 1..=BOUND_STEPS {
+                let time = pair[0] + (pair[1] - pair[0]) * (step as f64) / (BOUND_STEPS as f64);
+
+                swept = swept.union(&bounds.transform(&self.at(time)));
+            }
+        }
+
+        let pad = swept.extent() * 0.01;
+
+        BoundingBox::new(swept.min - &pad, swept.max + &pad)
+    }
+}
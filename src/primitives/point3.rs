@@ -2,7 +2,7 @@ use super::Vector3;
 
 use std::ops;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Point3 {
     pub point: [f64; 3],
 }
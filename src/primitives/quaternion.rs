@@ -0,0 +1,99 @@
+use super::*;
+
+// Unit quaternion w + xi + yj + zk describing a rotation
+#[derive(Debug, Clone, Copy)]
+pub struct Quaternion {
+    pub w: f64,
+    pub v: Vector3,
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self {
+            w,
+            v: Vector3::new(x, y, z),
+        }
+    }
+
+    // Rotation in the upper left 3x3 block of the matrix, which must be orthonormal
+    // with a positive determinant
+    pub fn from_matrix(m: &Matrix4) -> Self {
+        let trace = m[[0, 0]] + m[[1, 1]] + m[[2, 2]];
+
+        // Work from the largest of w, x, y and z to keep the square root away from 0
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+
+            Self::new(0.25 * s, (m[[2, 1]] - m[[1, 2]]) / s, (m[[0, 2]] - m[[2, 0]]) / s, (m[[1, 0]] - m[[0, 1]]) / s)
+        }
+        else if m[[0, 0]] > m[[1, 1]] && m[[0, 0]] > m[[2, 2]] {
+            let s = 2.0 * (1.0 + m[[0, 0]] - m[[1, 1]] - m[[2, 2]]).sqrt();
+
+            Self::new((m[[2, 1]] - m[[1, 2]]) / s, 0.25 * s, (m[[0, 1]] + m[[1, 0]]) / s, (m[[0, 2]] + m[[2, 0]]) / s)
+        }
+        else if m[[1, 1]] > m[[2, 2]] {
+            let s = 2.0 * (1.0 + m[[1, 1]] - m[[0, 0]] - m[[2, 2]]).sqrt();
+
+            Self::new((m[[0, 2]] - m[[2, 0]]) / s, (m[[0, 1]] + m[[1, 0]]) / s, 0.25 * s, (m[[1, 2]] + m[[2, 1]]) / s)
+        }
+        else {
+            let s = 2.0 * (1.0 + m[[2, 2]] - m[[0, 0]] - m[[1, 1]]).sqrt();
+
+            Self::new((m[[1, 0]] - m[[0, 1]]) / s, (m[[0, 2]] + m[[2, 0]]) / s, (m[[1, 2]] + m[[2, 1]]) / s, 0.25 * s)
+        };
+
+        q.norm()
+    }
+}
+
+impl Quaternion {
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.v.dot(&other.v)
+    }
+
+    pub fn norm(&self) -> Quaternion {
+        let len = self.dot(self).sqrt();
+
+        Self {
+            w: self.w / len,
+            v: self.v / len,
+        }
+    }
+
+    // Spherical linear interpolation, turning the short way round at a constant rate
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion {
+        // q and -q are the same rotation, pick the one closer to self
+        let (other, cos_theta) = match self.dot(other) {
+            d if d < 0.0 => (Self { w: -other.w, v: other.v * -1.0 }, -d),
+            d => (*other, d),
+        };
+
+        // Nearly the same rotation, where the sine below loses all precision
+        if cos_theta > 0.9995 {
+            return Self {
+                w: self.w + (other.w - self.w) * t,
+                v: self.v + &((other.v - &self.v) * t),
+            }.norm();
+        }
+
+        let theta = cos_theta.clamp(-1.0, 1.0).acos();
+        let a = ((1.0 - t) * theta).sin() / theta.sin();
+        let b = (t * theta).sin() / theta.sin();
+
+        Self {
+            w: self.w * a + other.w * b,
+            v: self.v * a + &(other.v * b),
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let (w, x, y, z) = (self.w, self.v[0], self.v[1], self.v[2]);
+
+        Matrix4::new(
+            1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0,
+            2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0,
+            2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        )
+    }
+}
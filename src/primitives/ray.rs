@@ -4,6 +4,9 @@ use super::*;
 pub struct Ray {
     pub position: Point3,
     pub direction: Vector3,
+    // Moment within the camera's shutter interval the ray is traced at, which places
+    // moving shapes
+    pub time: f64,
 }
 
 impl Ray {
@@ -11,6 +14,7 @@ impl Ray {
         Self {
             position: Point3::new_empty(),
             direction: Vector3::new_empty(),
+            time: 0.0,
         }
     }
 
@@ -18,6 +22,7 @@ impl Ray {
         Self {
             position: Point3::new_empty(),
             direction: *direction,
+            time: 0.0,
         }
     }
 
@@ -25,6 +30,7 @@ impl Ray {
         Self {
             position: *position,
             direction: Vector3::new_empty(),
+            time: 0.0,
         }
     }
 }
\ No newline at end of file
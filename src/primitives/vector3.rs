@@ -1,6 +1,6 @@
 use std::ops;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vector3 {
     pub vec: [f64; 3],
 }
@@ -47,6 +47,14 @@ impl Vector3 {
             vec: self.vec.map(|elm| elm / vec_len)
         }
     }
+
+    // Two unit vectors completing a right-handed frame around this unit vector
+    pub fn orthonormal_basis(&self) -> (Vector3, Vector3) {
+        let helper = if self.vec[0].abs() > 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(1.0, 0.0, 0.0) };
+        let t = helper.cross(self).norm();
+
+        (t, self.cross(&t))
+    }
 }
 
 impl ops::Add <&Vector3> for Vector3 {
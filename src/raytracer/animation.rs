@@ -0,0 +1,84 @@
+use crate::primitives::*;
+
+// Inclusive range of frames to render. Times in a scene are measured in frames, frame
+// n covering the times from n to n + 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+// Camera placement and lens at a point in time. The camera's other settings, such as
+// its projection and shutter, are the same in every frame.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub camera: Camera,
+}
+
+impl FrameRange {
+    pub fn new(first: usize, last: usize) -> Self {
+        Self {
+            first,
+            last,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        (self.last + 1).saturating_sub(self.first)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn frames(&self) -> std::ops::RangeInclusive <usize> {
+        self.first..=self.last
+    }
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn lerp_vec3(a: &Vector3, b: &Vector3, t: f64) -> Vector3 {
+    *a + &((*b - a) * t)
+}
+
+// The base camera moved to where the keyframes put it at the given time, changing
+// linearly between them and staying at the first and last one before and after them.
+// keyframes must be sorted by time.
+pub fn camera_at(base: &Camera, keyframes: &[CameraKeyframe], time: f64) -> Camera {
+    let (a, b, t) = match keyframes.iter().position(|k| k.time > time) {
+        None => match keyframes.last() {
+            Some(k) => (k, k, 0.0),
+            None => return *base,
+        },
+        Some(0) => (&keyframes[0], &keyframes[0], 0.0),
+        Some(i) => {
+            let (a, b) = (&keyframes[i - 1], &keyframes[i]);
+
+            (a, b, (time - a.time) / (b.time - a.time))
+        },
+    };
+
+    let mut camera = *base;
+
+    camera.eye = lerp_vec3(&a.camera.eye, &b.camera.eye, t);
+    camera.center = lerp_vec3(&a.camera.center, &b.camera.center, t);
+    camera.up = lerp_vec3(&a.camera.up, &b.camera.up, t);
+    camera.fovy = lerp(a.camera.fovy, b.camera.fovy, t);
+    camera.aperture = lerp(a.camera.aperture, b.camera.aperture, t);
+    camera.focus_distance = lerp(a.camera.focus_distance, b.camera.focus_distance, t);
+
+    camera
+}
+
+// Numbered file name for a frame, putting the number before the extension: out.png
+// becomes out_0001.png for frame 1
+pub fn frame_file_name(file_path_str: &str, frame: usize) -> String {
+    match file_path_str.rfind('.') {
+        Some(dot) if !file_path_str[dot..].contains(['/', '\\']) => format!("{}_{:04}{}", &file_path_str[..dot], frame, &file_path_str[dot..]),
+        _ => format!("{}_{:04}", file_path_str, frame),
+    }
+}
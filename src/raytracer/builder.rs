@@ -0,0 +1,469 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::primitives::*;
+use crate::geometry::*;
+use super::{Scene, SceneError, SceneErrorKind, IntegratorKind, ToneMapping, read_obj_file, read_ply_file};
+
+// Builds a scene from code the way a scene file would, keeping a transform stack and a
+// current material that apply to the shapes added after them. Calls can be chained:
+//
+//     let scene = SceneBuilder::new()
+//         .size(640, 480)
+//         .camera(Point3::new(0.0, -5.0, 2.0), Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), 45.0)
+//         .point_light(Point3::new(2.0, -2.0, 5.0), RGBColor::new(1.0, 1.0, 1.0))
+//         .diffuse(RGBColor::new(0.8, 0.2, 0.2))
+//         .sphere(Point3::new(0.0, 0.0, 1.0), 1.0)
+//         .build()?;
+//
+// Invalid input doesn't interrupt the chain. The first problem is kept and returned by
+// build, and the calls after it are still applied.
+pub struct SceneBuilder {
+    scene: Scene,
+    transf_stack: Vec <Matrix4>,
+    current_material: Material,
+    // Sample count given to area lights added from here on
+    light_samples: usize,
+    has_camera: bool,
+    error: Option <SceneError>,
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Self {
+            scene: Scene::new(),
+            transf_stack: vec![Matrix4::new_on_diag(1.0)],
+            current_material: Material::new(),
+            light_samples: 16,
+            has_camera: false,
+            error: None,
+        }
+    }
+
+    // Keeps the first problem only, later ones are often caused by it
+    fn fail(&mut self, method: &str, kind: SceneErrorKind) -> &mut Self {
+        if self.error.is_none() {
+            self.error = Some(SceneError::new(0, 0, method, kind));
+        }
+
+        self
+    }
+
+    fn check(&mut self, method: &str, valid: bool, problem: &str) -> bool {
+        if !valid {
+            self.fail(method, SceneErrorKind::InvalidValue(problem.to_string()));
+        }
+
+        valid
+    }
+
+    fn current_transform(&self) -> Matrix4 {
+        match self.transf_stack.last() {
+            Some(t) => *t,
+            None => Matrix4::new_on_diag(1.0),
+        }
+    }
+
+    fn right_mul_transf_stack(&mut self, m: &Matrix4) -> &mut Self {
+        if let Some(t) = self.transf_stack.last_mut() {
+            *t = *t * m;
+        }
+
+        self
+    }
+
+    fn push_area_light(&mut self, shape: AreaLightShape, color: RGBColor) -> &mut Self {
+        let mut light = AreaLight::new(shape, color);
+        light.samples = self.light_samples;
+
+        self.scene.lights.lights.push(LightType::Area(light));
+        self
+    }
+
+    // Returns the finished scene, ready to render, and resets the builder. Fails with
+    // the first problem found, or when the image size or camera were never set.
+    pub fn build(&mut self) -> Result <Scene, SceneError> {
+        if self.scene.img_width == 0 || self.scene.img_height == 0 {
+            self.fail("build", SceneErrorKind::InvalidValue("image size was never set".to_string()));
+        }
+
+        if !self.has_camera {
+            self.fail("build", SceneErrorKind::InvalidValue("camera was never set".to_string()));
+        }
+
+        let builder = std::mem::take(self);
+
+        if let Some(e) = builder.error {
+            return Err(e);
+        }
+
+        let mut scene = builder.scene;
+        scene.build_bvh();
+
+        Ok(scene)
+    }
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Image and rendering settings
+impl SceneBuilder {
+    pub fn size(&mut self, width: usize, height: usize) -> &mut Self {
+        if self.check("size", width > 0 && height > 0, "image size must be positive") {
+            self.scene.img_width = width;
+            self.scene.img_height = height;
+        }
+
+        self
+    }
+
+    pub fn max_depth(&mut self, depth: usize) -> &mut Self {
+        self.scene.max_recurse_depth = depth;
+        self
+    }
+
+    pub fn samples(&mut self, samples_per_pixel: usize) -> &mut Self {
+        if self.check("samples", samples_per_pixel > 0, "samples per pixel must be positive") {
+            self.scene.sampling.samples_per_pixel = samples_per_pixel;
+        }
+
+        self
+    }
+
+    pub fn integrator(&mut self, integrator: IntegratorKind) -> &mut Self {
+        self.scene.integrator = integrator;
+        self
+    }
+
+    pub fn tone_mapping(&mut self, tone_mapping: ToneMapping) -> &mut Self {
+        self.scene.tone_mapping = tone_mapping;
+        self
+    }
+
+    // Image file the scene asks to be written to
+    pub fn output(&mut self, file_path_str: &str) -> &mut Self {
+        self.scene.output = Some(file_path_str.to_string());
+        self
+    }
+
+    // fovy is the vertical field of view in degrees
+    pub fn camera(&mut self, eye: Point3, center: Point3, up: Vector3, fovy: f64) -> &mut Self {
+        let view = center - &eye;
+
+        if !self.check("camera", view.len() > 0.0, "eye and center must differ")
+            || !self.check("camera", up.cross(&view).len() > 0.0, "up must not be parallel to the viewing direction")
+            || !self.check("camera", fovy > 0.0 && fovy < 180.0, "field of view must be between 0 and 180 degrees") {
+            return self;
+        }
+
+        self.scene.camera.eye = eye.pos_rep();
+        self.scene.camera.center = center.pos_rep();
+        self.scene.camera.up = up;
+        self.scene.camera.fovy = fovy;
+        self.has_camera = true;
+
+        self
+    }
+
+    pub fn projection(&mut self, projection: Projection) -> &mut Self {
+        self.scene.camera.projection = projection;
+        self
+    }
+}
+
+// Lights
+impl SceneBuilder {
+    pub fn directional_light(&mut self, direction: Vector3, color: RGBColor) -> &mut Self {
+        if self.check("directional_light", direction.len() > 0.0, "direction must not be zero") {
+            self.scene.lights.lights.push(LightType::Directional(DirectionalLight { direction, color }));
+        }
+
+        self
+    }
+
+    pub fn point_light(&mut self, position: Point3, color: RGBColor) -> &mut Self {
+        self.scene.lights.lights.push(LightType::Point(PointLight { position, color }));
+        self
+    }
+
+    // Constant, linear and quadratic falloff of point lights
+    pub fn attenuation(&mut self, constant: f64, linear: f64, quadratic: f64) -> &mut Self {
+        self.scene.lights.attenuation = [constant, linear, quadratic];
+        self
+    }
+
+    // Shadow rays traced per shaded point for area lights added from here on
+    pub fn light_samples(&mut self, samples: usize) -> &mut Self {
+        if self.check("light_samples", samples > 0, "light samples must be positive") {
+            self.light_samples = samples;
+        }
+
+        self
+    }
+
+    // Emits from the side edge_u x edge_v faces
+    pub fn rect_light(&mut self, corner: Point3, edge_u: Vector3, edge_v: Vector3, color: RGBColor) -> &mut Self {
+        if !self.check("rect_light", edge_u.cross(&edge_v).len() > 0.0, "edges must span an area") {
+            return self;
+        }
+
+        self.push_area_light(AreaLightShape::Rectangle { corner, edge_u, edge_v }, color)
+    }
+
+    pub fn disk_light(&mut self, center: Point3, normal: Vector3, radius: f64, color: RGBColor) -> &mut Self {
+        if !self.check("disk_light", radius > 0.0 && normal.len() > 0.0, "radius and normal must not be zero") {
+            return self;
+        }
+
+        self.push_area_light(AreaLightShape::Disk { center, normal, radius }, color)
+    }
+
+    pub fn sphere_light(&mut self, center: Point3, radius: f64, color: RGBColor) -> &mut Self {
+        if !self.check("sphere_light", radius > 0.0, "radius must be positive") {
+            return self;
+        }
+
+        self.push_area_light(AreaLightShape::Sphere { center, radius }, color)
+    }
+}
+
+// Material given to the shapes added from here on
+impl SceneBuilder {
+    pub fn material(&mut self, material: Material) -> &mut Self {
+        self.current_material = material;
+        self
+    }
+
+    pub fn ambient(&mut self, color: RGBColor) -> &mut Self {
+        self.current_material.ambient = color;
+        self
+    }
+
+    pub fn diffuse(&mut self, color: RGBColor) -> &mut Self {
+        self.current_material.diffuse = color;
+        self
+    }
+
+    pub fn specular(&mut self, color: RGBColor) -> &mut Self {
+        self.current_material.specular = color;
+        self
+    }
+
+    pub fn emission(&mut self, color: RGBColor) -> &mut Self {
+        self.current_material.emission = color;
+        self
+    }
+
+    pub fn shininess(&mut self, shininess: f64) -> &mut Self {
+        self.current_material.shininess = shininess;
+        self
+    }
+}
+
+// Transform applied to the shapes added from here on, composed like the scene file's
+// transform commands
+impl SceneBuilder {
+    pub fn translate(&mut self, offset: Vector3) -> &mut Self {
+        self.right_mul_transf_stack(&Matrix4::new_translate(offset[0], offset[1], offset[2]))
+    }
+
+    pub fn rotate(&mut self, axis: Vector3, degrees: f64) -> &mut Self {
+        if !self.check("rotate", axis.len() > 0.0, "rotation axis must not be zero") {
+            return self;
+        }
+
+        self.right_mul_transf_stack(&Matrix4::new_rotate(&axis, degrees))
+    }
+
+    pub fn scale(&mut self, factors: Vector3) -> &mut Self {
+        self.right_mul_transf_stack(&Matrix4::new_scale(factors[0], factors[1], factors[2]))
+    }
+
+    pub fn push_transform(&mut self) -> &mut Self {
+        let t = self.current_transform();
+
+        self.transf_stack.push(t);
+        self
+    }
+
+    pub fn pop_transform(&mut self) -> &mut Self {
+        // The bottom of the stack is the scene's base transform
+        if self.transf_stack.len() <= 1 {
+            return self.fail("pop_transform", SceneErrorKind::TransformStackUnderflow);
+        }
+
+        self.transf_stack.pop();
+        self
+    }
+}
+
+// Shapes, placed with the current transform and material
+impl SceneBuilder {
+    // Adds a shape as it is, without applying the current transform or material
+    pub fn shape<S: Shape + 'static>(&mut self, shape: S) -> &mut Self {
+        self.scene.shapes.push(shape);
+        self
+    }
+
+    pub fn sphere(&mut self, center: Point3, radius: f64) -> &mut Self {
+        if !self.check("sphere", radius > 0.0, "radius must be positive") {
+            return self;
+        }
+
+        let mut new_sphere = Sphere::new();
+
+        new_sphere.center = center;
+        new_sphere.radius = radius;
+        new_sphere.material = self.current_material;
+        new_sphere.transform = self.current_transform();
+
+        self.shape(new_sphere)
+    }
+
+    pub fn triangle(&mut self, vertices: [Point3; 3]) -> &mut Self {
+        let mut new_tri = Triangle::new();
+
+        new_tri.vertices = vertices;
+        new_tri.material = self.current_material;
+        new_tri.transform = self.current_transform();
+
+        self.shape(new_tri)
+    }
+
+    // Triangle shaded smoothly with the given normal at each vertex
+    pub fn triangle_with_normals(&mut self, vertices: [Point3; 3], normals: [Vector3; 3]) -> &mut Self {
+        let mut new_tri = Triangle::new();
+
+        new_tri.vertices = vertices;
+        new_tri.normals = Some(normals);
+        new_tri.material = self.current_material;
+        new_tri.transform = self.current_transform();
+
+        self.shape(new_tri)
+    }
+
+    pub fn plane(&mut self, point: Point3, normal: Vector3) -> &mut Self {
+        if !self.check("plane", normal.len() > 0.0, "normal must not be zero") {
+            return self;
+        }
+
+        let mut new_plane = Plane::new();
+
+        new_plane.point = point;
+        new_plane.normal = normal;
+        new_plane.material = self.current_material;
+        new_plane.transform = self.current_transform();
+
+        self.shape(new_plane)
+    }
+
+    // Axis-aligned box between two opposite corners
+    pub fn cuboid(&mut self, corner_a: Point3, corner_b: Point3) -> &mut Self {
+        let mut new_box = Cuboid::new();
+
+        new_box.min = Point3::new(corner_a[0].min(corner_b[0]), corner_a[1].min(corner_b[1]), corner_a[2].min(corner_b[2]));
+        new_box.max = Point3::new(corner_a[0].max(corner_b[0]), corner_a[1].max(corner_b[1]), corner_a[2].max(corner_b[2]));
+        new_box.material = self.current_material;
+        new_box.transform = self.current_transform();
+
+        self.shape(new_box)
+    }
+
+    // Capped cylinder standing on center, along the y axis
+    pub fn cylinder(&mut self, center: Point3, radius: f64, height: f64) -> &mut Self {
+        if !self.check("cylinder", radius > 0.0 && height > 0.0, "radius and height must be positive") {
+            return self;
+        }
+
+        let mut new_cylinder = Cylinder::new();
+
+        new_cylinder.center = center;
+        new_cylinder.radius = radius;
+        new_cylinder.height = height;
+        new_cylinder.material = self.current_material;
+        new_cylinder.transform = self.current_transform();
+
+        self.shape(new_cylinder)
+    }
+
+    // Cone with its base on center, pointing along the y axis
+    pub fn cone(&mut self, center: Point3, radius: f64, height: f64) -> &mut Self {
+        if !self.check("cone", radius > 0.0 && height > 0.0, "radius and height must be positive") {
+            return self;
+        }
+
+        let mut new_cone = Cone::new();
+
+        new_cone.center = center;
+        new_cone.radius = radius;
+        new_cone.height = height;
+        new_cone.material = self.current_material;
+        new_cone.transform = self.current_transform();
+
+        self.shape(new_cone)
+    }
+
+    pub fn disk(&mut self, center: Point3, normal: Vector3, radius: f64) -> &mut Self {
+        if !self.check("disk", radius > 0.0 && normal.len() > 0.0, "radius and normal must not be zero") {
+            return self;
+        }
+
+        let mut new_disk = Disk::new();
+
+        new_disk.center = center;
+        new_disk.normal = normal;
+        new_disk.radius = radius;
+        new_disk.material = self.current_material;
+        new_disk.transform = self.current_transform();
+
+        self.shape(new_disk)
+    }
+
+    // Torus around the y axis
+    pub fn torus(&mut self, center: Point3, major_radius: f64, minor_radius: f64) -> &mut Self {
+        if !self.check("torus", major_radius > 0.0 && minor_radius > 0.0, "radii must be positive") {
+            return self;
+        }
+
+        let mut new_torus = Torus::new();
+
+        new_torus.center = center;
+        new_torus.major_radius = major_radius;
+        new_torus.minor_radius = minor_radius;
+        new_torus.material = self.current_material;
+        new_torus.transform = self.current_transform();
+
+        self.shape(new_torus)
+    }
+
+    fn push_mesh(&mut self, mesh: Arc <TriangleMesh>, source: Option <String>) -> &mut Self {
+        let mut new_mesh = Mesh::new(mesh);
+
+        new_mesh.source = source;
+        new_mesh.material = self.current_material;
+        new_mesh.transform = self.current_transform();
+
+        self.shape(new_mesh)
+    }
+
+    // Places mesh geometry that may be shared with other shapes
+    pub fn mesh(&mut self, mesh: Arc <TriangleMesh>) -> &mut Self {
+        self.push_mesh(mesh, None)
+    }
+
+    // Loads a Wavefront OBJ or PLY file, chosen by extension, and places it
+    pub fn mesh_file<P: AsRef <Path>>(&mut self, file_path: P) -> &mut Self {
+        let path = file_path.as_ref();
+        let is_ply = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("ply"));
+        let loaded = if is_ply { read_ply_file(path) } else { read_obj_file(path) };
+
+        match loaded {
+            Ok(mesh) => self.push_mesh(Arc::new(mesh), Some(path.display().to_string())),
+            Err(error) => self.fail("mesh_file", SceneErrorKind::MeshLoad { path: path.display().to_string(), error }),
+        }
+    }
+}
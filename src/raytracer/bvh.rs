@@ -0,0 +1,327 @@
+use crate::primitives::*;
+
+const SAH_BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECT_COST: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: BoundingBox,
+    // Interior nodes: index of the left child, the right child comes right after it
+    // Leaf nodes: index of the first entry in Bvh::indices
+    first: usize,
+    // Number of primitives in a leaf, 0 for interior nodes
+    count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec <BvhNode>,
+    indices: Vec <usize>,
+    // Primitives with infinite bounds, kept out of the hierarchy and tested against
+    // every ray
+    unbounded: Vec <usize>,
+    // Length of the primitive list the hierarchy was built over
+    prim_count: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SahBin {
+    bounds: BoundingBox,
+    count: usize,
+}
+
+impl Default for Bvh {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bvh {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            indices: Vec::new(),
+            unbounded: Vec::new(),
+            prim_count: 0,
+        }
+    }
+
+    // Builds the hierarchy over primitives given by their world-space bounds. The
+    // primitive indices handed back during traversal are indices into prim_bounds.
+    // Primitives with empty bounds can never be hit and are left out.
+    pub fn build(prim_bounds: &[BoundingBox]) -> Self {
+        let mut bvh = Self::new();
+        bvh.prim_count = prim_bounds.len();
+
+        let (indices, unbounded) = (0..prim_bounds.len())
+            .filter(|&i| !prim_bounds[i].is_empty())
+            .partition(|&i| prim_bounds[i].is_finite());
+        bvh.indices = indices;
+        bvh.unbounded = unbounded;
+
+        if bvh.indices.is_empty() {
+            return bvh;
+        }
+
+        let centroids: Vec <Point3> = prim_bounds.iter().map(|b| b.centroid()).collect();
+
+        bvh.nodes.push(BvhNode {
+            bounds: BoundingBox::new_empty(),
+            first: 0,
+            count: bvh.indices.len(),
+        });
+        bvh.subdivide(0, prim_bounds, &centroids);
+
+        bvh
+    }
+
+    pub fn is_built_for(&self, prim_count: usize) -> bool {
+        self.prim_count == prim_count
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty() && self.unbounded.is_empty()
+    }
+
+    // Bounds of the primitives in the hierarchy, leaving out unbounded ones
+    pub fn bounds(&self) -> BoundingBox {
+        match self.nodes.first() {
+            Some(root) => root.bounds,
+            None => BoundingBox::new_empty(),
+        }
+    }
+
+    fn subdivide(&mut self, node_idx: usize, prim_bounds: &[BoundingBox], centroids: &[Point3]) {
+        let BvhNode { first, count, .. } = self.nodes[node_idx];
+        let node_prims = &self.indices[first..(first + count)];
+
+        let bounds = node_prims.iter().fold(BoundingBox::new_empty(), |acc, &i| acc.union(&prim_bounds[i]));
+        let centroid_bounds = node_prims.iter().fold(BoundingBox::new_empty(), |acc, &i| acc.grow(&centroids[i]));
+        self.nodes[node_idx].bounds = bounds;
+
+        if count <= 1 {
+            return;
+        }
+
+        let leaf_cost = INTERSECT_COST * (count as f64);
+        let split = Self::find_sah_split(node_prims, prim_bounds, centroids, &centroid_bounds, bounds.surface_area())
+            .filter(|&(_, _, cost)| cost < leaf_cost || count > MAX_LEAF_SIZE);
+
+        // Fall back to a median split on the widest axis when SAH cannot separate the
+        // centroids (e.g. many coincident primitives) but the leaf would be too big
+        let mid = match split {
+            Some((axis, bin, _)) => {
+                let extent = centroid_bounds.extent()[axis];
+                let lo = centroid_bounds.min[axis];
+
+                self.partition(first, count, |i| Self::bin_index(centroids[i][axis], lo, extent) <= bin)
+            },
+            None if count > MAX_LEAF_SIZE => {
+                let axis = centroid_bounds.largest_axis();
+                self.indices[first..(first + count)].sort_by(|&a, &b| centroids[a][axis].total_cmp(&centroids[b][axis]));
+
+                first + count / 2
+            },
+            None => return,
+        };
+
+        if mid == first || mid == first + count {
+            return;
+        }
+
+        let left_idx = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: BoundingBox::new_empty(),
+            first,
+            count: mid - first,
+        });
+        self.nodes.push(BvhNode {
+            bounds: BoundingBox::new_empty(),
+            first: mid,
+            count: first + count - mid,
+        });
+
+        self.nodes[node_idx].first = left_idx;
+        self.nodes[node_idx].count = 0;
+
+        self.subdivide(left_idx, prim_bounds, centroids);
+        self.subdivide(left_idx + 1, prim_bounds, centroids);
+    }
+
+    fn bin_index(value: f64, lo: f64, extent: f64) -> usize {
+        (((value - lo) / extent * (SAH_BINS as f64)) as usize).min(SAH_BINS - 1)
+    }
+
+    // Binned surface area heuristic. Returns the axis, the last bin going to the left
+    // child and the estimated cost of the split.
+    fn find_sah_split(node_prims: &[usize], prim_bounds: &[BoundingBox], centroids: &[Point3], centroid_bounds: &BoundingBox, node_area: f64) -> Option <(usize, usize, f64)> {
+        let mut best: Option <(usize, usize, f64)> = None;
+
+        for axis in 0..3 {
+            let extent = centroid_bounds.extent()[axis];
+            let lo = centroid_bounds.min[axis];
+
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bins = [SahBin { bounds: BoundingBox::new_empty(), count: 0 }; SAH_BINS];
+
+            for &i in node_prims {
+                let bin = &mut bins[Self::bin_index(centroids[i][axis], lo, extent)];
+                bin.bounds = bin.bounds.union(&prim_bounds[i]);
+                bin.count += 1;
+            }
+
+            // Sweep from the right to get the area and count of every right-hand side
+            let mut right_area = [0.0; SAH_BINS];
+            let mut right_count = [0usize; SAH_BINS];
+            let mut acc = SahBin { bounds: BoundingBox::new_empty(), count: 0 };
+
+            for b in (1..SAH_BINS).rev() {
+                acc.bounds = acc.bounds.union(&bins[b].bounds);
+                acc.count += bins[b].count;
+                right_area[b] = acc.bounds.surface_area();
+                right_count[b] = acc.count;
+            }
+
+            let mut acc = SahBin { bounds: BoundingBox::new_empty(), count: 0 };
+
+            for b in 0..(SAH_BINS - 1) {
+                acc.bounds = acc.bounds.union(&bins[b].bounds);
+                acc.count += bins[b].count;
+
+                if acc.count == 0 || right_count[b + 1] == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST + INTERSECT_COST
+                    * (acc.bounds.surface_area() * (acc.count as f64) + right_area[b + 1] * (right_count[b + 1] as f64))
+                    / node_area;
+
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, b, cost));
+                }
+            }
+        }
+
+        best
+    }
+
+    // Moves all primitives satisfying goes_left to the front of the range, returning the split point
+    fn partition<F>(&mut self, first: usize, count: usize, goes_left: F) -> usize
+    where F: Fn(usize) -> bool, {
+        let mut mid = first;
+
+        for i in first..(first + count) {
+            if goes_left(self.indices[i]) {
+                self.indices.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        mid
+    }
+}
+
+impl Bvh {
+    // Finds the nearest primitive hit closer than max_dist. test is called with a
+    // primitive index and the current nearest distance, and should return the
+    // world-space distance from the ray origin to the hit along with its payload.
+    pub fn intersect_nearest<T, F>(&self, ray: &Ray, max_dist: f64, mut test: F) -> Option <(f64, T)>
+    where F: FnMut(usize, f64) -> Option <(f64, T)>, {
+        let mut nearest: Option <(f64, T)> = None;
+
+        self.traverse(ray, max_dist, |prim, nearest_dist| {
+            if let Some((dist, data)) = test(prim, *nearest_dist) {
+                if dist < *nearest_dist {
+                    *nearest_dist = dist;
+                    nearest = Some((dist, data));
+                }
+            }
+
+            false
+        });
+
+        nearest
+    }
+
+    // Returns true as soon as test reports a hit for any primitive whose bounds the
+    // ray enters before max_dist
+    pub fn intersect_any<F>(&self, ray: &Ray, max_dist: f64, mut test: F) -> bool
+    where F: FnMut(usize) -> bool, {
+        self.traverse(ray, max_dist, |prim, _| test(prim))
+    }
+
+    // Depth-first, near child first. visit may shrink the search distance, and
+    // traversal stops early once it returns true.
+    fn traverse<V>(&self, ray: &Ray, max_dist: f64, mut visit: V) -> bool
+    where V: FnMut(usize, &mut f64) -> bool, {
+        let mut max_dist = max_dist;
+
+        for &prim in &self.unbounded {
+            if visit(prim, &mut max_dist) {
+                return true;
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let dir_len = ray.direction.len();
+        let inv_dir = Vector3::new(dir_len / ray.direction[0], dir_len / ray.direction[1], dir_len / ray.direction[2]);
+
+        let mut stack: Vec <(usize, f64)> = match self.nodes[0].bounds.intersect_ray(ray, &inv_dir, max_dist) {
+            Some(dist) => vec![(0, dist)],
+            None => return false,
+        };
+
+        while let Some((node_idx, entry_dist)) = stack.pop() {
+            // A nearer hit may have been found since this node was pushed
+            if entry_dist > max_dist {
+                continue;
+            }
+
+            let node = &self.nodes[node_idx];
+
+            if node.count > 0 {
+                for &prim in &self.indices[node.first..(node.first + node.count)] {
+                    if visit(prim, &mut max_dist) {
+                        return true;
+                    }
+                }
+
+                continue;
+            }
+
+            let (left, right) = (node.first, node.first + 1);
+            let dist_left = self.nodes[left].bounds.intersect_ray(ray, &inv_dir, max_dist);
+            let dist_right = self.nodes[right].bounds.intersect_ray(ray, &inv_dir, max_dist);
+
+            match (dist_left, dist_right) {
+                (Some(l), Some(r)) => {
+                    if l <= r {
+                        stack.push((right, r));
+                        stack.push((left, l));
+                    }
+                    else {
+                        stack.push((left, l));
+                        stack.push((right, r));
+                    }
+                },
+                (Some(l), None) => stack.push((left, l)),
+                (None, Some(r)) => stack.push((right, r)),
+                (None, None) => (),
+            }
+        }
+
+        false
+    }
+}
@@ -1,6 +1,6 @@
-use crate::{primitives::{Vector3, Ray, Point3}, geometry::{RGBColor, LightStack, ShapeType, LightType, PointLight, Material}};
+use crate::{primitives::{Vector3, Ray, Point3}, geometry::{RGBColor, LightStack, LightType, PointLight, AreaLight}};
 
-use super::{Scene, IntersectData, Intersectable, intersect_scene_from_shape};
+use super::{Rng, SamplePattern, Scene, IntersectData, intersect_scene_from_shape, intersect_scene_from_surface, occluded_from_shape};
 
 fn get_light_intensity(
     light_dir: Vector3,
@@ -17,36 +17,87 @@ fn get_light_intensity(
     (lambert + &phong) * &light_color
 }
 
+// Direction of a ray passing from a medium into one with relative index of refraction
+// 1 / eta, None on total internal reflection. surf_norm faces the incoming ray.
+pub fn refract(direction: Vector3, surf_norm: Vector3, eta: f64) -> Option <Vector3> {
+    let cos_i = -direction.dot(&surf_norm);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    Some((direction * eta + &(surf_norm * (eta * cos_i - (1.0 - sin2_t).sqrt()))).norm())
+}
+
+fn trace_from_surface(direction: Vector3, scene: &Scene, intersect_pt: IntersectData, now_recurse_depth: usize) -> RGBColor {
+    let new_ray = Ray {
+        position: intersect_pt.coords,
+        direction,
+        time: intersect_pt.time,
+    };
+
+    match intersect_scene_from_surface(new_ray, scene, intersect_pt) {
+        Some(intersected_obj) => get_color_recursive(new_ray, scene, intersected_obj, now_recurse_depth + 1),
+        None => RGBColor::new_empty(),
+    }
+}
+
+// Fresnel weighted reflection and refraction. Surfaces are only lit from the outside,
+// and light arriving from the inside has been absorbed along its way through.
+fn get_transmissive_color(ray: Ray, scene: &Scene, intersect_pt: IntersectData, now_recurse_depth: usize) -> RGBColor {
+    let material = intersect_pt.material;
+    let direction = ray.direction.norm();
+    let entering = direction.dot(&intersect_pt.geom_normal) < 0.0;
+
+    let (facing_norm, n1, n2) = if entering {
+        (intersect_pt.normal, 1.0, material.ior)
+    }
+    else {
+        (intersect_pt.normal * -1.0, material.ior, 1.0)
+    };
+    let cos_i = (-direction.dot(&facing_norm)).clamp(0.0, 1.0);
+
+    let reflect_dir = (direction - &(facing_norm * (2.0 * direction.dot(&facing_norm)))).norm();
+    let mut new_color = match refract(direction, facing_norm, n1 / n2) {
+        Some(refract_dir) => {
+            let reflectance = material.fresnel.reflectance(cos_i, n1, n2);
+            let transmitted = trace_from_surface(refract_dir, scene, intersect_pt, now_recurse_depth) * &material.transmission;
+
+            trace_from_surface(reflect_dir, scene, intersect_pt, now_recurse_depth) * reflectance + &(transmitted * (1.0 - reflectance))
+        },
+        None => trace_from_surface(reflect_dir, scene, intersect_pt, now_recurse_depth),
+    };
+
+    if entering {
+        new_color = new_color + &get_color(ray, scene, intersect_pt, &scene.lights);
+    }
+    else {
+        let dist = (intersect_pt.coords - &ray.position).len();
+        let absorption = material.absorption;
+
+        new_color = new_color * &RGBColor::new((-absorption[0] * dist).exp(), (-absorption[1] * dist).exp(), (-absorption[2] * dist).exp());
+    }
+
+    new_color
+}
+
 pub fn get_color_recursive(ray: Ray, scene: &Scene, intersect_pt: IntersectData, now_recurse_depth: usize) -> RGBColor {
     if now_recurse_depth > scene.max_recurse_depth {
         RGBColor::new_empty()
     }
+    else if intersect_pt.material.is_transmissive() {
+        get_transmissive_color(ray, scene, intersect_pt, now_recurse_depth)
+    }
     else {
         // Create reflection ray
-		// Transform normals to world space
-        let (vec_norm, specular) = match intersect_pt.shape {
-            ShapeType::Sphere(s) => {
-                // For spheres, get intersection point in object space before finding the normal
-                let intersect_obj_space = s.transform.mat_invtf_point3(&intersect_pt.coords);
-
-                (
-                    s.transform.mat_invtf_norm_vec3(&(intersect_obj_space - &s.center).norm()),
-                    s.material.specular,
-                )
-            },
-            ShapeType::Triangle(t) => {
-                // For triangles, we can directly get the normal using the vertices
-                (
-                    (t.vertices[1] - &t.vertices[0]).cross(&(t.vertices[2] - &t.vertices[0])).norm(),
-                    t.material.specular,
-                )
-            },
-            _ => (Vector3::new_empty(), Vector3::new_empty())
-        };
+        let vec_norm = intersect_pt.normal;
+        let specular = intersect_pt.material.specular;
 
         let reflect_ray = Ray {
             position: intersect_pt.coords,
             direction: (ray.direction - &(vec_norm * (2.0 * ray.direction.dot(&vec_norm)))).norm(),
+            time: ray.time,
         };
 
         let new_color = if let Some(intersected_obj) = intersect_scene_from_shape(reflect_ray, scene, intersect_pt) {
@@ -70,26 +121,8 @@ pub fn get_color_recursive(ray: Ray, scene: &Scene, intersect_pt: IntersectData,
 }
 
 pub fn get_color(ray: Ray, scene: &Scene, intersect_pt: IntersectData, lights: &LightStack) -> RGBColor {
-    // Transform normals to world space
-    let (vec_norm, material) = match intersect_pt.shape {
-        ShapeType::Sphere(s) => {
-            // For spheres, get intersection point in object space before finding the normal
-            let intersect_obj_space = s.transform.mat_invtf_point3(&intersect_pt.coords);
-
-            (
-                s.transform.mat_invtf_norm_vec3(&(intersect_obj_space - &s.center).norm()),
-                s.material,
-            )
-        },
-        ShapeType::Triangle(t) => {
-            // For triangles, we can directly get the normal using the vertices
-            (
-                (t.vertices[1] - &t.vertices[0]).cross(&(t.vertices[2] - &t.vertices[0])).norm(),
-                t.material,
-            )
-        },
-        _ => (Vector3::new_empty(), Material::new())
-    };
+    let vec_norm = intersect_pt.normal;
+    let material = intersect_pt.material;
 
     let eye_dir = (ray.position - &intersect_pt.coords).norm();
 
@@ -114,54 +147,47 @@ pub fn get_color(ray: Ray, scene: &Scene, intersect_pt: IntersectData, lights: &
                 else {
                     RGBColor::new_empty()
                 }
-            }
+            },
+            LightType::Area(a) => {
+                acc + &get_area_light_intensity(a, scene, intersect_pt, eye_dir)
+            },
         }
     });
 
     *diffspec + &material.ambient + &material.emission
 }
 
-// NOTE: Excluding intersected object only works for convex surfaces
-fn test_shadows(intersect_pt: IntersectData, light_pos: Point3, scene: &Scene) -> bool {
-    let ray = Ray {
-        position: intersect_pt.coords,
-        direction: (light_pos - &intersect_pt.coords).norm(),
-    };
+// Generator seeded from the shaded point, so that light sampling gives the same result
+// however the image is split among threads
+fn surface_rng(coords: Point3) -> Rng {
+    let seed = (0..3).fold(0xcbf29ce484222325_u64, |hash, i| (hash ^ coords[i].to_bits()).wrapping_mul(0x100000001b3));
 
-    for (i, each_shape) in scene.shapes.0.iter().enumerate() {
-        match each_shape {
-            ShapeType::Sphere(d) => {
-                if matches!(intersect_pt.shape, ShapeType::Sphere(_)) && intersect_pt.index == i {
-                    continue;
-                }
-                
-                if let Some(id) = d.intersect(&ray) {
-                    // Check if intersected surface is beyond the light source
-                    if (id.coords - &ray.position).dot(&(id.coords - &ray.position)).abs() > (light_pos - &ray.position).dot(&(light_pos - &ray.position)).abs() {
-                        continue;
-                    }
-                    else {
-                        return true;
-                    }
-                }
-            },
-            ShapeType::Triangle(t) => {
-                if matches!(intersect_pt.shape, ShapeType::Triangle(_)) && intersect_pt.index == i {
-                    continue;
-                }
-                
-                if let Some(id) = t.intersect(&ray) {
-                    if (id.coords - &ray.position).dot(&(id.coords - &ray.position)).abs() > (light_pos - &ray.position).dot(&(light_pos - &ray.position)).abs() {
-                        continue;
-                    }
-                    else {
-                        return true;
-                    }
-                }
-            }
-            _ => ()
+    Rng::new(seed)
+}
+
+// Average over stratified points on the light, each tested for shadows
+fn get_area_light_intensity(light: &AreaLight, scene: &Scene, intersect_pt: IntersectData, eye_dir: Vector3) -> RGBColor {
+    let material = intersect_pt.material;
+    let mut rng = surface_rng(intersect_pt.coords);
+    let positions = SamplePattern::Jittered.generate(light.samples, &mut rng);
+    let sample_count = positions.len() as f64;
+
+    positions.into_iter().fold(RGBColor::new_empty(), |acc, u| {
+        let sample = light.shape.sample(&intersect_pt.coords, u);
+
+        if sample.weight <= 0.0 || test_shadows(intersect_pt, sample.position, scene) {
+            return acc;
         }
-    }
 
-    false
+        let light_dir_i = (sample.position - &intersect_pt.coords).norm();
+        let half_vec_i = (light_dir_i + &eye_dir).norm();
+        let irradiance = light.color * (sample.weight / sample_count);
+
+        acc + &get_light_intensity(light_dir_i, irradiance, intersect_pt.normal, half_vec_i, material.diffuse, material.specular, material.shininess)
+    })
+}
+
+// NOTE: Excluding intersected object only works for convex surfaces
+fn test_shadows(intersect_pt: IntersectData, light_pos: Point3, scene: &Scene) -> bool {
+    occluded_from_shape(scene, intersect_pt, light_pos)
 }
\ No newline at end of file
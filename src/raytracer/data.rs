@@ -1,30 +1,74 @@
+use std::f64::consts::PI;
+
 use crate::primitives::*;
 use crate::geometry::*;
+use super::{Bvh, SamplingSettings, IntegratorKind, ToneMapping, FrameRange, CameraKeyframe, camera_at};
 
 #[derive(Debug, Clone, Copy)]
 pub struct IntersectData {
+    // Shape in Scene::shapes that was hit
     pub index: usize,
+    // Time of the ray that made the hit, which rays leaving it keep
+    pub time: f64,
+    // Triangle within a mesh, 0 for other shapes
+    pub face: usize,
     pub coords: Point3,
-    pub shape: ShapeType,
+    // World-space unit normal used for shading, interpolated from vertex normals
+    // where the shape has them
+    pub normal: Vector3,
+    // World-space unit normal of the actual surface
+    pub geom_normal: Vector3,
+    // Texture coordinates of the hit
+    pub uv: (f64, f64),
+    // UV units per world unit around the hit, used to size texture lookups
+    pub uv_density: f64,
+    // Material at the hit, with any textures already applied by intersect_scene
+    pub material: Material,
 }
 
 pub struct Scene {
     pub img_width: usize,
     pub img_height: usize,
     pub max_recurse_depth: usize,
+    // Image file named by the scene, None to leave the choice to the caller
+    pub output: Option <String>,
+    pub sampling: SamplingSettings,
+    pub integrator: IntegratorKind,
+    // How the image is brought down to 8 bits when saved to a low dynamic range format
+    pub tone_mapping: ToneMapping,
 
     pub camera: Camera,
+    // Camera poses over time, sorted by time. Empty for a camera that stays put.
+    pub camera_keyframes: Vec <CameraKeyframe>,
+    // Frames to render when animating, None for a still image. Only the camera and shape
+    // transforms change from frame to frame, materials and other parameters stay put.
+    pub frame_range: Option <FrameRange>,
+    // Start of the frame being rendered, which the shutter opens and closes relative to.
+    // Set with set_frame.
+    pub frame_time: f64,
     pub shapes: Shapes,
     pub vertices: VertexStack,
+    pub vertex_normals: VertexNormalStack,
+    pub vertex_uvs: VertexTexStack,
+    pub textures: Vec <Texture>,
     pub lights: LightStack,
+
+    // Acceleration structure over shapes, see Scene::build_bvh
+    pub bvh: Bvh,
 }
 
 impl IntersectData {
     pub fn new() -> Self {
         Self {
             index: 0,
+            time: 0.0,
+            face: 0,
             coords: Point3::new_empty(),
-            shape: ShapeType::None,
+            normal: Vector3::new_empty(),
+            geom_normal: Vector3::new_empty(),
+            uv: (0.0, 0.0),
+            uv_density: 0.0,
+            material: Material::new(),
         }
     }
 }
@@ -35,10 +79,74 @@ impl Scene {
             img_width: 0,
             img_height: 0,
             max_recurse_depth: 5,
+            output: None,
+            sampling: SamplingSettings::new(),
+            integrator: IntegratorKind::Whitted,
+            tone_mapping: ToneMapping::new(),
             camera: Camera::new(),
+            camera_keyframes: Vec::new(),
+            frame_range: None,
+            frame_time: 0.0,
             shapes: Shapes::new(),
             vertices: VertexStack::new(),
+            vertex_normals: VertexNormalStack::new(),
+            vertex_uvs: VertexTexStack::new(),
+            textures: Vec::new(),
             lights: LightStack::new(),
+            bvh: Bvh::new(),
+        }
+    }
+
+    // Angle between the rays through neighbouring samples, for estimating how much of
+    // a surface one sample covers
+    pub fn sample_spread_angle(&self) -> f64 {
+        let img_height = self.img_height.max(1) as f64;
+
+        let pixel_angle = match self.camera.projection {
+            Projection::Perspective => 2.0 * (0.5 * self.camera.fovy.to_radians()).tan() / img_height,
+            // Parallel rays don't spread, so this is the spread of a perspective view
+            // showing the same height at the center point
+            Projection::Orthographic { .. } => self.camera.ortho_height() / ((self.camera.center - &self.camera.eye).len().max(1e-9) * img_height),
+            Projection::Fisheye => self.camera.fovy.to_radians() / img_height,
+            Projection::Equirectangular => PI / img_height,
+        };
+
+        pixel_angle / (self.sampling.samples_per_pixel.max(1) as f64).sqrt()
+    }
+
+    // Times the shutter opens and closes in the current frame
+    pub fn shutter_interval(&self) -> (f64, f64) {
+        let open = self.frame_time + self.camera.shutter_open;
+
+        (open, open.max(self.frame_time + self.camera.shutter_close))
+    }
+
+    // Moves the camera, and the BVH if any shapes move, to the given frame. Shapes,
+    // including meshes and object definitions with their own BVHs, are shared by all
+    // frames.
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame_time = frame as f64;
+
+        if !self.camera_keyframes.is_empty() {
+            self.camera = camera_at(&self.camera, &self.camera_keyframes, self.frame_time);
+        }
+
+        // Bounds of shapes that stay put are the same for every frame
+        if self.has_moving_shapes() {
+            self.build_bvh();
         }
     }
+
+    pub fn has_moving_shapes(&self) -> bool {
+        self.shapes.0.iter().any(|s| s.moves())
+    }
+
+    // Must be called again whenever shapes are added or changed after parsing. Moving
+    // shapes are bounded for the current frame only.
+    pub fn build_bvh(&mut self) {
+        let (start, end) = self.shutter_interval();
+        let shape_bounds: Vec <BoundingBox> = self.shapes.0.iter().map(|s| s.bounds_between(start, end)).collect();
+
+        self.bvh = Bvh::build(&shape_bounds);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::io;
+
+use image::ImageError;
+
+#[derive(Debug)]
+pub enum SceneErrorKind {
+    Io(io::Error),
+    UnknownCommand,
+    MissingArguments { expected: usize, found: usize },
+    ExtraArguments { expected: usize, found: usize },
+    InvalidNumber(String),
+    InvalidOption { token: String, allowed: &'static [&'static str] },
+    VertexOutOfRange { index: usize, count: usize },
+    TransformStackUnderflow,
+    CsgStackUnderflow,
+    // Reported at the beginCsg of a group left open at the end of the file
+    UnclosedCsg,
+    EmptyCsg,
+    NotSolid,
+    ObjectStackUnderflow,
+    // Reported at the beginObject of a definition left open at the end of the file
+    UnclosedObject,
+    NestedObject,
+    EmptyObject,
+    UnknownObject(String),
+    MeshLoad { path: String, error: io::Error },
+    // Boxed to keep SceneError small
+    TextureLoad { path: String, error: Box <ImageError> },
+    // Rejected value, such as one passed to a SceneBuilder method
+    InvalidValue(String),
+}
+
+// Problem found while reading a scene. line and column are 1-based and point at the
+// offending token; both are 0 when the problem is not tied to a line (e.g. the file
+// cannot be opened, or the scene is made with a SceneBuilder and command names the
+// builder method).
+#[derive(Debug)]
+pub struct SceneError {
+    pub line: usize,
+    pub column: usize,
+    pub command: String,
+    pub kind: SceneErrorKind,
+}
+
+impl SceneError {
+    pub fn new(line: usize, column: usize, command: &str, kind: SceneErrorKind) -> Self {
+        Self {
+            line,
+            column,
+            command: command.to_string(),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for SceneErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneErrorKind::Io(e) => write!(f, "{}", e),
+            SceneErrorKind::UnknownCommand => write!(f, "unknown command"),
+            SceneErrorKind::MissingArguments { expected, found } => write!(f, "expected {} arguments, found {}", expected, found),
+            SceneErrorKind::ExtraArguments { expected, found } => write!(f, "expected at most {} arguments, found {}", expected, found),
+            SceneErrorKind::InvalidNumber(token) => write!(f, "invalid number \"{}\"", token),
+            SceneErrorKind::InvalidOption { token, allowed } => write!(f, "invalid option \"{}\", expected one of: {}", token, allowed.join(", ")),
+            SceneErrorKind::VertexOutOfRange { index, count } => write!(f, "vertex index {} out of range, {} vertices defined", index, count),
+            SceneErrorKind::TransformStackUnderflow => write!(f, "no transform left to pop"),
+            SceneErrorKind::CsgStackUnderflow => write!(f, "no CSG group left to end"),
+            SceneErrorKind::UnclosedCsg => write!(f, "CSG group is never ended"),
+            SceneErrorKind::EmptyCsg => write!(f, "CSG group has no shapes"),
+            SceneErrorKind::NotSolid => write!(f, "shape does not enclose a volume and cannot be used in CSG"),
+            SceneErrorKind::ObjectStackUnderflow => write!(f, "no object definition to end"),
+            SceneErrorKind::UnclosedObject => write!(f, "object definition is never ended"),
+            SceneErrorKind::NestedObject => write!(f, "objects cannot be defined inside other objects or CSG groups"),
+            SceneErrorKind::EmptyObject => write!(f, "object has no shapes"),
+            SceneErrorKind::UnknownObject(name) => write!(f, "no object named \"{}\"", name),
+            SceneErrorKind::MeshLoad { path, error } => write!(f, "cannot load mesh \"{}\": {}", path, error),
+            SceneErrorKind::TextureLoad { path, error } => write!(f, "cannot load texture \"{}\": {}", path, error),
+            SceneErrorKind::InvalidValue(problem) => write!(f, "{}", problem),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 && self.command.is_empty() {
+            write!(f, "{}", self.kind)
+        }
+        else if self.line == 0 {
+            write!(f, "{}: {}", self.command, self.kind)
+        }
+        else {
+            write!(f, "line {}, column {} ({}): {}", self.line, self.column, self.command, self.kind)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option <&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            SceneErrorKind::Io(e) => Some(e),
+            SceneErrorKind::MeshLoad { error, .. } => Some(error),
+            SceneErrorKind::TextureLoad { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
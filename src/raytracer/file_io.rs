@@ -1,272 +1,1136 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, BufRead};
-use std::path::Path;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use image::DynamicImage;
+use image::{DynamicImage, ImageError, ImageResult, Rgb};
+use image::codecs::hdr::HdrEncoder;
 
-use crate::raytracer::Scene;
+use crate::raytracer::{Scene, SceneError, SceneErrorKind, SamplePattern, PixelFilter, IntegratorKind, Framebuffer, ToneMapping, ToneMapOperator, FrameRange, CameraKeyframe, build_image, build_hdr_image, read_obj_file, read_ply_file};
 use crate::primitives::*;
 use crate::geometry::*;
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where P: AsRef<Path>, {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+// Formats that keep the framebuffer's full floating-point range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrFormat {
+    OpenExr,
+    // Radiance RGBE (.hdr)
+    Radiance,
+    // Portable float map
+    Pfm,
 }
 
-fn right_mul_transf_stack(m: &Matrix4, transf_stack: &mut Vec <Matrix4>) {
-    if let Some(t) = transf_stack.last_mut() {
-        *t = *t * m;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    // Any problem in the file is an error
+    Strict,
+    // Lines with problems are skipped and reported as warnings
+    Lenient,
+}
+
+// A single scene file line split into whitespace-separated tokens, each with its
+// 1-based column
+struct Command<'a> {
+    line: usize,
+    name: &'a str,
+    column: usize,
+    args: Vec <(usize, &'a str)>,
+}
+
+// Level of the transform stack. Once keyframes are recorded on it, shapes move through
+// them, and transforms given after the last one place shapes within the moving frame.
+#[derive(Clone)]
+struct TransformLevel {
+    transform: Matrix4,
+    keyframes: Vec <Keyframe>,
+}
+
+// Boolean combination being defined between beginCsg and endCsg
+struct CsgGroup {
+    line: usize,
+    column: usize,
+    csg: Csg,
+}
+
+// Object being defined between beginObject and endObject
+struct ObjectGroup {
+    line: usize,
+    column: usize,
+    name: String,
+    shapes: Shapes,
+    // Transform stack of the scene, set aside while the object's shapes are placed
+    // relative to the object
+    outer_transf_stack: Vec <TransformLevel>,
+}
+
+struct SceneParser {
+    mode: ParseMode,
+    // Directory that paths in the scene file are relative to
+    base_dir: PathBuf,
+    scene: Scene,
+    transf_stack: Vec <TransformLevel>,
+    // Open CSG groups, innermost last. Shapes go into the innermost one.
+    csg_stack: Vec <CsgGroup>,
+    object_group: Option <ObjectGroup>,
+    objects: HashMap <String, Arc <ObjectDefinition>>,
+    current_material: Material,
+    // Sample count given to area lights defined from here on
+    light_samples: usize,
+    // Meshes already loaded, so that importing a file again shares its geometry
+    mesh_cache: HashMap <PathBuf, Arc <TriangleMesh>>,
+    // Images already loaded, shared by all textures using them
+    texture_cache: HashMap <PathBuf, Arc <MipMap>>,
+    warnings: Vec <SceneError>,
+}
+
+fn tokenize(cmd_line: &str) -> Vec <(usize, &str)> {
+    let mut tokens: Vec <(usize, &str)> = Vec::new();
+    let mut token_start: Option <usize> = None;
+
+    for (i, c) in cmd_line.char_indices() {
+        match (c.is_whitespace(), token_start) {
+            (true, Some(s)) => {
+                tokens.push((s, &cmd_line[s..i]));
+                token_start = None;
+            },
+            (false, None) => token_start = Some(i),
+            _ => (),
+        }
+    }
+
+    if let Some(s) = token_start {
+        tokens.push((s, &cmd_line[s..]));
+    }
+
+    tokens.into_iter().map(|(s, t)| (cmd_line[..s].chars().count() + 1, t)).collect()
+}
+
+impl<'a> Command<'a> {
+    // Returns None for blank and comment lines
+    fn parse(line: usize, cmd_line: &'a str) -> Option <Self> {
+        let tokens = tokenize(cmd_line);
+        let (column, name) = *tokens.first()?;
+
+        if name.starts_with('#') {
+            return None;
+        }
+
+        Some(Self {
+            line,
+            name,
+            column,
+            args: tokens[1..].to_vec(),
+        })
+    }
+
+    fn error(&self, column: usize, kind: SceneErrorKind) -> SceneError {
+        SceneError::new(self.line, column, self.name, kind)
+    }
+
+    fn expect_args(&self, min: usize) -> Result <(), SceneError> {
+        if self.args.len() < min {
+            Err(self.error(self.column, SceneErrorKind::MissingArguments { expected: min, found: self.args.len() }))
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    // Checked separately from expect_args, since extra arguments are harmless in lenient mode
+    fn check_extra_args(&self, max: usize) -> Result <(), SceneError> {
+        if self.args.len() > max {
+            Err(self.error(self.args[max].0, SceneErrorKind::ExtraArguments { expected: max, found: self.args.len() }))
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    fn f64_arg(&self, i: usize) -> Result <f64, SceneError> {
+        let (column, token) = self.args[i];
+
+        token.parse::<f64>().map_err(|_| self.error(column, SceneErrorKind::InvalidNumber(token.to_string())))
+    }
+
+    fn usize_arg(&self, i: usize) -> Result <usize, SceneError> {
+        let (column, token) = self.args[i];
+
+        token.parse::<usize>().map_err(|_| self.error(column, SceneErrorKind::InvalidNumber(token.to_string())))
     }
 
+    // Keyword argument, looked up with from_name
+    fn option_arg<T, F>(&self, i: usize, allowed: &'static [&'static str], from_name: F) -> Result <T, SceneError>
+    where F: Fn(&str) -> Option <T>, {
+        let (column, token) = self.args[i];
+
+        from_name(token).ok_or_else(|| self.error(column, SceneErrorKind::InvalidOption { token: token.to_string(), allowed }))
+    }
+
+    // Three consecutive numbers starting at argument i
+    fn vec3_arg(&self, i: usize) -> Result <Vector3, SceneError> {
+        Ok(Vector3::new(self.f64_arg(i)?, self.f64_arg(i + 1)?, self.f64_arg(i + 2)?))
+    }
+
+    fn point3_arg(&self, i: usize) -> Result <Point3, SceneError> {
+        Ok(Point3 { point: self.vec3_arg(i)?.vec })
+    }
+}
+
+// Minimum and maximum number of arguments taken by each known command, None for
+// unknown commands
+fn cmd_arg_count(name: &str) -> Option <(usize, usize)> {
+    match name {
+        "size" => Some((2, 2)),
+        "maxdepth" => Some((1, 1)),
+        "output" => Some((1, 1)),
+        "samples" => Some((1, 2)),
+        "filter" => Some((1, 2)),
+        "integrator" => Some((1, 1)),
+        "tonemap" => Some((1, 3)),
+        "camera" => Some((10, 10)),
+        "aperture" => Some((2, 4)),
+        "shutter" => Some((2, 2)),
+        "cameraKeyframe" => Some((1, 1)),
+        "frames" => Some((2, 2)),
+        "projection" => Some((1, 2)),
+        "directional" | "point" => Some((6, 6)),
+        "rectlight" => Some((12, 12)),
+        "disklight" => Some((10, 10)),
+        "spherelight" => Some((7, 7)),
+        "lightsamples" => Some((1, 1)),
+        "attenuation" | "ambient" | "diffuse" | "specular" | "emission" => Some((3, 3)),
+        "transmission" | "absorption" => Some((3, 3)),
+        "shininess" | "ior" | "fresnel" => Some((1, 1)),
+        "maxverts" | "maxvertnorms" => Some((1, 1)),
+        "vertex" | "tri" | "trinormal" | "tritex" => Some((3, 3)),
+        "vertexnormal" => Some((6, 6)),
+        "vertextex" => Some((5, 5)),
+        "texture" => Some((2, 4)),
+        "sphere" => Some((4, 4)),
+        "plane" | "box" => Some((6, 6)),
+        "cylinder" | "cone" | "torus" => Some((5, 5)),
+        "disk" => Some((7, 7)),
+        "scale" | "translate" => Some((3, 3)),
+        "rotate" => Some((4, 4)),
+        "transform" => Some((16, 16)),
+        "pushTransform" | "popTransform" => Some((0, 0)),
+        "keyframe" => Some((1, 1)),
+        "beginCsg" => Some((1, 1)),
+        "endCsg" => Some((0, 0)),
+        "beginObject" => Some((1, 1)),
+        "endObject" => Some((0, 0)),
+        "instance" => Some((1, 2)),
+        "obj" | "ply" => Some((1, 1)),
+        _ => None,
+    }
+}
+
+// maxverts and maxvertnorms are only hints, so a huge count can't claim memory up front
+const MAX_RESERVED_VERTICES: usize = 1 << 20;
+
+const SAMPLE_PATTERNS: &[&str] = &["grid", "jittered", "halton"];
+const PIXEL_FILTERS: &[&str] = &["box", "tent", "gaussian", "mitchell"];
+const INTEGRATORS: &[&str] = &["whitted", "path"];
+const TONE_MAP_OPERATORS: &[&str] = &["clamp", "reinhard", "aces"];
+const ENCODINGS: &[&str] = &["linear", "srgb"];
+const MATERIAL_CHANNELS: &[&str] = &["ambient", "diffuse", "specular", "emission"];
+const TEXTURE_FILTERS: &[&str] = &["nearest", "bilinear", "trilinear"];
+const WRAP_MODES: &[&str] = &["repeat", "clamp", "mirror"];
+const FRESNEL_MODELS: &[&str] = &["schlick", "exact"];
+const PROJECTIONS: &[&str] = &["perspective", "orthographic", "fisheye", "equirectangular"];
+const CSG_OPS: &[&str] = &["union", "intersection", "difference"];
+const INSTANCE_MATERIALS: &[&str] = &["keep", "override"];
+
+impl TransformLevel {
+    fn new() -> Self {
+        Self {
+            transform: Matrix4::new_on_diag(1.0),
+            keyframes: Vec::new(),
+        }
+    }
 }
 
-fn match_cmd(cmd_line: String, transf_stack: &mut Vec <Matrix4>, current_material: &mut Material, scene_info: &mut Scene) {
-    let cmd_tokens: Vec <&str> = cmd_line.split_whitespace().collect();
+impl SceneParser {
+    fn new(mode: ParseMode, base_dir: PathBuf) -> Self {
+        Self {
+            mode,
+            base_dir,
+            scene: Scene::new(),
+            transf_stack: vec![TransformLevel::new()],
+            csg_stack: Vec::new(),
+            object_group: None,
+            objects: HashMap::new(),
+            current_material: Material::new(),
+            light_samples: 16,
+            mesh_cache: HashMap::new(),
+            texture_cache: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
 
-    if cmd_tokens.len() <= 0 {
-        return;
+    // Strict mode turns the problem into an error, lenient mode records it and carries on
+    fn report(&mut self, result: Result <(), SceneError>) -> Result <(), SceneError> {
+        match (result, self.mode) {
+            (Err(e), ParseMode::Lenient) => {
+                self.warnings.push(e);
+                Ok(())
+            },
+            (result, _) => result,
+        }
     }
 
-    let arg_tokens = &cmd_tokens[1..];
+    fn parse_line(&mut self, line: usize, cmd_line: &str) -> Result <(), SceneError> {
+        let cmd = match Command::parse(line, cmd_line) {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        let (min_args, max_args) = match cmd_arg_count(cmd.name) {
+            Some(n) => n,
+            None => return self.report(Err(cmd.error(cmd.column, SceneErrorKind::UnknownCommand))),
+        };
+
+        let extra_args = cmd.check_extra_args(max_args);
+        self.report(extra_args)?;
 
-    match cmd_tokens[0] {
-        "size" => {
-            if arg_tokens.len() >= 2 {
-                if let (Ok(w), Ok(h)) = (arg_tokens[0].parse::<usize>(), arg_tokens[1].parse::<usize>()) {
-                    scene_info.img_width = w;
-                    scene_info.img_height = h;
+        let result = cmd.expect_args(min_args).and_then(|_| self.match_cmd(&cmd));
+        self.report(result)
+    }
+
+    fn right_mul_transf_stack(&mut self, m: &Matrix4) {
+        if let Some(t) = self.transf_stack.last_mut() {
+            t.transform = t.transform * m;
+        }
+    }
+
+    // Transform for new shapes, relative to the moving frame while there are keyframes
+    fn current_transform(&self) -> Matrix4 {
+        match self.transf_stack.last() {
+            Some(t) => match t.keyframes.last() {
+                Some(k) => t.transform.relative_to(&k.transform),
+                None => t.transform,
+            },
+            None => Matrix4::new_on_diag(1.0),
+        }
+    }
+
+    fn current_motion(&self) -> Option <MotionTransform> {
+        match self.transf_stack.last() {
+            Some(t) if !t.keyframes.is_empty() => Some(MotionTransform::new(&t.keyframes)),
+            _ => None,
+        }
+    }
+
+    // Adds a shape placed with current_transform, setting it moving if there are keyframes
+    fn push_shape<S: Shape + 'static>(&mut self, cmd: &Command, shape: S) -> Result <(), SceneError> {
+        match self.current_motion() {
+            Some(motion) => self.add_shape(cmd, Moving::new(shape, motion)),
+            None => self.add_shape(cmd, shape),
+        }
+    }
+
+    fn add_shape<S: Shape + 'static>(&mut self, cmd: &Command, shape: S) -> Result <(), SceneError> {
+        match self.csg_stack.last_mut() {
+            Some(group) => {
+                if !shape.is_solid() {
+                    return Err(cmd.error(cmd.column, SceneErrorKind::NotSolid));
                 }
-            }
-        },
-        "maxdepth" => {
-            if arg_tokens.len() >= 1 {
-                if let Ok(d) = arg_tokens[0].parse::<usize>() {
-                    scene_info.max_recurse_depth = d;
+
+                group.csg.push(shape);
+            },
+            None => match &mut self.object_group {
+                Some(group) => group.shapes.push(shape),
+                None => self.scene.shapes.push(shape),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn push_area_light(&mut self, shape: AreaLightShape, color: RGBColor) {
+        let mut light = AreaLight::new(shape, color);
+        light.samples = self.light_samples;
+
+        self.scene.lights.lights.push(LightType::Area(light));
+    }
+
+    fn load_mesh(&mut self, cmd: &Command) -> Result <Arc <TriangleMesh>, SceneError> {
+        let (column, path_str) = cmd.args[0];
+        let path = self.base_dir.join(path_str);
+
+        if let Some(mesh) = self.mesh_cache.get(&path) {
+            return Ok(mesh.clone());
+        }
+
+        let loaded = if cmd.name == "ply" { read_ply_file(&path) } else { read_obj_file(&path) };
+
+        match loaded {
+            Ok(mesh) => {
+                let mesh = Arc::new(mesh);
+                self.mesh_cache.insert(path, mesh.clone());
+
+                Ok(mesh)
+            },
+            Err(error) => Err(cmd.error(column, SceneErrorKind::MeshLoad { path: path_str.to_string(), error })),
+        }
+    }
+
+    fn load_texture_image(&mut self, cmd: &Command, i: usize) -> Result <Arc <MipMap>, SceneError> {
+        let (column, path_str) = cmd.args[i];
+        let path = self.base_dir.join(path_str);
+
+        if let Some(image) = self.texture_cache.get(&path) {
+            return Ok(image.clone());
+        }
+
+        match image::open(&path) {
+            Ok(loaded) => {
+                let image = Arc::new(MipMap::from_image(&loaded));
+                self.texture_cache.insert(path, image.clone());
+
+                Ok(image)
+            },
+            Err(error) => Err(cmd.error(column, SceneErrorKind::TextureLoad { path: path_str.to_string(), error: Box::new(error) })),
+        }
+    }
+
+    // Arguments have already been counted against cmd_arg_count
+    fn match_cmd(&mut self, cmd: &Command) -> Result <(), SceneError> {
+        match cmd.name {
+            "size" => {
+                self.scene.img_width = cmd.usize_arg(0)?;
+                self.scene.img_height = cmd.usize_arg(1)?;
+            },
+            "maxdepth" => {
+                self.scene.max_recurse_depth = cmd.usize_arg(0)?;
+            },
+            "output" => {
+                self.scene.output = Some(cmd.args[0].1.to_string());
+            },
+            "samples" => {
+                let count = cmd.usize_arg(0)?;
+
+                if cmd.args.len() > 1 {
+                    self.scene.sampling.pattern = cmd.option_arg(1, SAMPLE_PATTERNS, SamplePattern::from_name)?;
                 }
-            }
-        },
-        "camera" => {
-            if arg_tokens.len() >= 10 {
-                for i in 0..3 {
-                    if let (Ok(e), Ok(c), Ok(u)) = (arg_tokens[i].parse::<f64>(), arg_tokens[i + 3].parse::<f64>(), arg_tokens[i + 6].parse::<f64>()) {
-                        scene_info.camera.eye[i] = e;
-                        scene_info.camera.center[i] = c;
-                        scene_info.camera.up[i] = u;
-                    }
+
+                self.scene.sampling.samples_per_pixel = count;
+            },
+            "filter" => {
+                let mut filter = cmd.option_arg(0, PIXEL_FILTERS, PixelFilter::from_name)?;
+
+                if cmd.args.len() > 1 {
+                    filter = filter.with_radius(cmd.f64_arg(1)?);
                 }
 
-                if let Ok(f) = arg_tokens[9].parse::<f64>() {
-                    scene_info.camera.fovy = f;
+                self.scene.sampling.filter = filter;
+            },
+            "integrator" => {
+                self.scene.integrator = cmd.option_arg(0, INTEGRATORS, IntegratorKind::from_name)?;
+            },
+            "tonemap" => {
+                let mut tone_mapping = ToneMapping::new();
+
+                tone_mapping.operator = cmd.option_arg(0, TONE_MAP_OPERATORS, ToneMapOperator::from_name)?;
+
+                if cmd.args.len() > 1 {
+                    tone_mapping.exposure = cmd.f64_arg(1)?;
                 }
-            }
-        },
-        "directional" => {
-            if arg_tokens.len() >= 6 {
-                let mut light_dir = Vector3::new_empty();
-                let mut color = RGBColor::new_empty();
 
+                if cmd.args.len() > 2 {
+                    tone_mapping.srgb = cmd.option_arg(2, ENCODINGS, |e| match e {
+                        "linear" => Some(false),
+                        "srgb" => Some(true),
+                        _ => None,
+                    })?;
+                }
 
-                for i in 0..3 {
-                    if let (Ok(d), Ok(c)) = (arg_tokens[i].parse::<f64>(), arg_tokens[i + 3].parse::<f64>()) {
-                        light_dir[i] = d;
-                        color[i] = c;
+                self.scene.tone_mapping = tone_mapping;
+            },
+            "camera" => {
+                let eye = cmd.vec3_arg(0)?;
+                let center = cmd.vec3_arg(3)?;
+                let up = cmd.vec3_arg(6)?;
+                let fovy = cmd.f64_arg(9)?;
+
+                self.scene.camera.eye = eye;
+                self.scene.camera.center = center;
+                self.scene.camera.up = up;
+                self.scene.camera.fovy = fovy;
+            },
+            "projection" => {
+                let mut projection = cmd.option_arg(0, PROJECTIONS, Projection::from_name)?;
+
+                if let Projection::Orthographic { height } = &mut projection {
+                    if cmd.args.len() > 1 {
+                        *height = cmd.f64_arg(1)?;
                     }
                 }
+                else {
+                    // Only orthographic projections take a second argument
+                    let extra_args = cmd.check_extra_args(1);
+                    self.report(extra_args)?;
+                }
 
-                scene_info.lights.lights.push(LightType::Directional(DirectionalLight {
-                    direction: light_dir,
-                    color: color,
-                }));
-            }
-        },
-        "point" => {
-            if arg_tokens.len() >= 6 {
-                let mut light_pos = Point3::new_empty();
-                let mut color = RGBColor::new_empty();
+                self.scene.camera.projection = projection;
+            },
+            "aperture" => {
+                self.scene.camera.aperture = cmd.f64_arg(0)?;
+                self.scene.camera.focus_distance = cmd.f64_arg(1)?;
+                self.scene.camera.blades = if cmd.args.len() > 2 { cmd.usize_arg(2)? } else { 0 };
+                self.scene.camera.blade_rotation = if cmd.args.len() > 3 { cmd.f64_arg(3)? } else { 0.0 };
+            },
+            "shutter" => {
+                self.scene.camera.shutter_open = cmd.f64_arg(0)?;
+                self.scene.camera.shutter_close = cmd.f64_arg(1)?;
+            },
+            "cameraKeyframe" => {
+                let time = cmd.f64_arg(0)?;
 
-                for i in 0..3 {
-                    if let (Ok(d), Ok(c)) = (arg_tokens[i].parse::<f64>(), arg_tokens[i + 3].parse::<f64>()) {
-                        light_pos[i] = d;
-                        color[i] = c;
-                    }
+                self.scene.camera_keyframes.push(CameraKeyframe { time, camera: self.scene.camera });
+            },
+            "frames" => {
+                let first = cmd.usize_arg(0)?;
+                let last = cmd.usize_arg(1)?;
+
+                if last < first {
+                    return Err(cmd.error(cmd.args[1].0, SceneErrorKind::InvalidValue(format!("last frame {} comes before first frame {}", last, first))));
                 }
 
-                scene_info.lights.lights.push(LightType::Point(PointLight {
+                self.scene.frame_range = Some(FrameRange::new(first, last));
+            },
+            "directional" => {
+                let light_dir = cmd.vec3_arg(0)?;
+                let color = cmd.vec3_arg(3)?;
+
+                self.scene.lights.lights.push(LightType::Directional(DirectionalLight {
+                    direction: light_dir,
+                    color,
+                }));
+            },
+            "point" => {
+                let light_pos = cmd.point3_arg(0)?;
+                let color = cmd.vec3_arg(3)?;
+
+                self.scene.lights.lights.push(LightType::Point(PointLight {
                     position: light_pos,
-                    color: color,
+                    color,
                 }));
-            }
-        },
-        "attenuation" => {
-            if arg_tokens.len() >= 3 {
+            },
+            "rectlight" => {
+                let shape = AreaLightShape::Rectangle {
+                    corner: cmd.point3_arg(0)?,
+                    edge_u: cmd.vec3_arg(3)?,
+                    edge_v: cmd.vec3_arg(6)?,
+                };
+
+                self.push_area_light(shape, cmd.vec3_arg(9)?);
+            },
+            "disklight" => {
+                let shape = AreaLightShape::Disk {
+                    center: cmd.point3_arg(0)?,
+                    normal: cmd.vec3_arg(3)?,
+                    radius: cmd.f64_arg(6)?,
+                };
+
+                self.push_area_light(shape, cmd.vec3_arg(7)?);
+            },
+            "spherelight" => {
+                let shape = AreaLightShape::Sphere {
+                    center: cmd.point3_arg(0)?,
+                    radius: cmd.f64_arg(3)?,
+                };
+
+                self.push_area_light(shape, cmd.vec3_arg(4)?);
+            },
+            "lightsamples" => {
+                self.light_samples = cmd.usize_arg(0)?;
+            },
+            "attenuation" => {
+                self.scene.lights.attenuation = cmd.vec3_arg(0)?.vec;
+            },
+            "ambient" => {
+                self.current_material.ambient = cmd.vec3_arg(0)?;
+            },
+            "diffuse" => {
+                self.current_material.diffuse = cmd.vec3_arg(0)?;
+            },
+            "specular" => {
+                self.current_material.specular = cmd.vec3_arg(0)?;
+            },
+            "emission" => {
+                self.current_material.emission = cmd.vec3_arg(0)?;
+            },
+            "shininess" => {
+                self.current_material.shininess = cmd.f64_arg(0)?;
+            },
+            "transmission" => {
+                self.current_material.transmission = cmd.vec3_arg(0)?;
+            },
+            "ior" => {
+                self.current_material.ior = cmd.f64_arg(0)?;
+            },
+            "absorption" => {
+                self.current_material.absorption = cmd.vec3_arg(0)?;
+            },
+            "fresnel" => {
+                self.current_material.fresnel = cmd.option_arg(0, FRESNEL_MODELS, FresnelModel::from_name)?;
+            },
+            // Only capacity hints, files may go on to define more vertices than they declare
+            "maxverts" => {
+                let count = cmd.usize_arg(0)?.min(MAX_RESERVED_VERTICES);
+                let vertices = &mut self.scene.vertices.0;
+
+                vertices.try_reserve(count.saturating_sub(vertices.len())).map_err(|e| cmd.error(cmd.args[0].0, SceneErrorKind::InvalidValue(e.to_string())))?;
+            },
+            "maxvertnorms" => {
+                let count = cmd.usize_arg(0)?.min(MAX_RESERVED_VERTICES);
+                let vertex_normals = &mut self.scene.vertex_normals.0;
+
+                vertex_normals.try_reserve(count.saturating_sub(vertex_normals.len())).map_err(|e| cmd.error(cmd.args[0].0, SceneErrorKind::InvalidValue(e.to_string())))?;
+            },
+            "vertex" => {
+                let new_vertex = cmd.point3_arg(0)?;
+
+                self.scene.vertices.0.push(new_vertex);
+            },
+            "tri" => {
+                let mut new_tri = Triangle::new();
+
                 for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        scene_info.lights.attenuation[i] = v;
-                    }
+                    let v_idx = cmd.usize_arg(i)?;
+
+                    new_tri.vertices[i] = match self.scene.vertices.0.get(v_idx) {
+                        Some(v) => *v,
+                        None => return Err(cmd.error(cmd.args[i].0, SceneErrorKind::VertexOutOfRange {
+                            index: v_idx,
+                            count: self.scene.vertices.0.len(),
+                        })),
+                    };
                 }
-            }
-        },
-        "ambient" => {
-            if arg_tokens.len() >= 3 {
-                for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        current_material.ambient[i] = v;
-                    }
+
+                new_tri.material = self.current_material;
+                new_tri.transform = self.current_transform();
+
+                self.push_shape(cmd, new_tri)?;
+            },
+            "vertexnormal" => {
+                let new_vertex = cmd.point3_arg(0)?;
+                let new_normal = cmd.vec3_arg(3)?;
+
+                self.scene.vertex_normals.0.push((new_vertex, new_normal));
+            },
+            "trinormal" => {
+                let mut new_tri = Triangle::new();
+                let mut normals = [Vector3::new_empty(); 3];
+
+                for (i, normal) in normals.iter_mut().enumerate() {
+                    let v_idx = cmd.usize_arg(i)?;
+
+                    (new_tri.vertices[i], *normal) = match self.scene.vertex_normals.0.get(v_idx) {
+                        Some(v) => *v,
+                        None => return Err(cmd.error(cmd.args[i].0, SceneErrorKind::VertexOutOfRange {
+                            index: v_idx,
+                            count: self.scene.vertex_normals.0.len(),
+                        })),
+                    };
                 }
-            }
-        },
-        "diffuse" => {
-            if arg_tokens.len() >= 3 {
-                for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        current_material.diffuse[i] = v;
-                    }
+
+                new_tri.normals = Some(normals);
+                new_tri.material = self.current_material;
+                new_tri.transform = self.current_transform();
+
+                self.push_shape(cmd, new_tri)?;
+            },
+            "vertextex" => {
+                let new_vertex = cmd.point3_arg(0)?;
+                let new_uv = (cmd.f64_arg(3)?, cmd.f64_arg(4)?);
+
+                self.scene.vertex_uvs.0.push((new_vertex, new_uv));
+            },
+            "tritex" => {
+                let mut new_tri = Triangle::new();
+                let mut uvs = [(0.0, 0.0); 3];
+
+                for (i, uv) in uvs.iter_mut().enumerate() {
+                    let v_idx = cmd.usize_arg(i)?;
+
+                    (new_tri.vertices[i], *uv) = match self.scene.vertex_uvs.0.get(v_idx) {
+                        Some(v) => *v,
+                        None => return Err(cmd.error(cmd.args[i].0, SceneErrorKind::VertexOutOfRange {
+                            index: v_idx,
+                            count: self.scene.vertex_uvs.0.len(),
+                        })),
+                    };
                 }
-            }
-        },
-        "specular" => {
-            if arg_tokens.len() >= 3 {
-                for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        current_material.specular[i] = v;
-                    }
+
+                new_tri.uvs = Some(uvs);
+                new_tri.material = self.current_material;
+                new_tri.transform = self.current_transform();
+
+                self.push_shape(cmd, new_tri)?;
+            },
+            "texture" => {
+                let channel = cmd.option_arg(0, MATERIAL_CHANNELS, MaterialChannel::from_name)?;
+
+                // "none" unbinds the channel's texture
+                if cmd.args[1].1 == "none" {
+                    *self.current_material.textures.get_mut(channel) = None;
+                    return Ok(());
                 }
-            }
-        },
-        "emission" => {
-            if arg_tokens.len() >= 3 {
-                for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        current_material.emission[i] = v;
-                    }
+
+                let mut texture = Texture::new(self.load_texture_image(cmd, 1)?);
+                texture.source = Some(cmd.args[1].1.to_string());
+
+                if cmd.args.len() > 2 {
+                    texture.filter = cmd.option_arg(2, TEXTURE_FILTERS, TextureFilter::from_name)?;
                 }
-            }
-        },
-        "shininess" => {
-            if arg_tokens.len() >= 1 {
-                if let Ok(v) = arg_tokens[0].parse::<f64>() {
-                    current_material.shininess = v;
+
+                if cmd.args.len() > 3 {
+                    texture.wrap = cmd.option_arg(3, WRAP_MODES, WrapMode::from_name)?;
                 }
-            }
-        },
-        "vertex" => {
-            if arg_tokens.len() >= 3 {
-                let mut new_vertex = Point3::new_empty();
 
-                for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        new_vertex[i] = v;
-                    }
+                self.scene.textures.push(texture);
+                *self.current_material.textures.get_mut(channel) = Some(self.scene.textures.len() - 1);
+            },
+            "sphere" => {
+                let mut new_sphere = Sphere::new();
+
+                new_sphere.center = cmd.point3_arg(0)?;
+                new_sphere.radius = cmd.f64_arg(3)?;
+                new_sphere.material = self.current_material;
+                new_sphere.transform = self.current_transform();
+
+                self.push_shape(cmd, new_sphere)?;
+            },
+            "plane" => {
+                let mut new_plane = Plane::new();
+
+                new_plane.point = cmd.point3_arg(0)?;
+                new_plane.normal = cmd.vec3_arg(3)?;
+                new_plane.material = self.current_material;
+                new_plane.transform = self.current_transform();
+
+                self.push_shape(cmd, new_plane)?;
+            },
+            "box" => {
+                let (p0, p1) = (cmd.point3_arg(0)?, cmd.point3_arg(3)?);
+                let mut new_box = Cuboid::new();
+
+                new_box.min = Point3::new(p0[0].min(p1[0]), p0[1].min(p1[1]), p0[2].min(p1[2]));
+                new_box.max = Point3::new(p0[0].max(p1[0]), p0[1].max(p1[1]), p0[2].max(p1[2]));
+                new_box.material = self.current_material;
+                new_box.transform = self.current_transform();
+
+                self.push_shape(cmd, new_box)?;
+            },
+            "cylinder" => {
+                let mut new_cylinder = Cylinder::new();
+
+                new_cylinder.center = cmd.point3_arg(0)?;
+                new_cylinder.radius = cmd.f64_arg(3)?;
+                new_cylinder.height = cmd.f64_arg(4)?;
+                new_cylinder.material = self.current_material;
+                new_cylinder.transform = self.current_transform();
+
+                self.push_shape(cmd, new_cylinder)?;
+            },
+            "cone" => {
+                let mut new_cone = Cone::new();
+
+                new_cone.center = cmd.point3_arg(0)?;
+                new_cone.radius = cmd.f64_arg(3)?;
+                new_cone.height = cmd.f64_arg(4)?;
+                new_cone.material = self.current_material;
+                new_cone.transform = self.current_transform();
+
+                self.push_shape(cmd, new_cone)?;
+            },
+            "disk" => {
+                let mut new_disk = Disk::new();
+
+                new_disk.center = cmd.point3_arg(0)?;
+                new_disk.normal = cmd.vec3_arg(3)?;
+                new_disk.radius = cmd.f64_arg(6)?;
+                new_disk.material = self.current_material;
+                new_disk.transform = self.current_transform();
+
+                self.push_shape(cmd, new_disk)?;
+            },
+            "torus" => {
+                let mut new_torus = Torus::new();
+
+                new_torus.center = cmd.point3_arg(0)?;
+                new_torus.major_radius = cmd.f64_arg(3)?;
+                new_torus.minor_radius = cmd.f64_arg(4)?;
+                new_torus.material = self.current_material;
+                new_torus.transform = self.current_transform();
+
+                self.push_shape(cmd, new_torus)?;
+            },
+            "obj" | "ply" => {
+                let mut new_mesh = Mesh::new(self.load_mesh(cmd)?);
+
+                new_mesh.source = Some(cmd.args[0].1.to_string());
+                new_mesh.material = self.current_material;
+                new_mesh.transform = self.current_transform();
+
+                self.push_shape(cmd, new_mesh)?;
+            },
+            "scale" => {
+                let s = cmd.vec3_arg(0)?;
+
+                self.right_mul_transf_stack(&Matrix4::new_scale(s[0], s[1], s[2]));
+            },
+            "rotate" => {
+                let axis = cmd.vec3_arg(0)?;
+                let degrees = cmd.f64_arg(3)?;
+
+                self.right_mul_transf_stack(&Matrix4::new_rotate(&axis, degrees));
+            },
+            "translate" => {
+                let t = cmd.vec3_arg(0)?;
+
+                self.right_mul_transf_stack(&Matrix4::new_translate(t[0], t[1], t[2]));
+            },
+            // Any matrix, given row by row
+            "transform" => {
+                let mut m = Matrix4::new_empty();
+
+                for i in 0..16 {
+                    m[[i / 4, i % 4]] = cmd.f64_arg(i)?;
                 }
 
-                scene_info.vertices.0.push(new_vertex);
-            }
-        },
-        "tri" => {
-            if arg_tokens.len() >= 3 {
-                let mut new_tri = Triangle::new();
+                self.right_mul_transf_stack(&m);
+            },
+            "pushTransform" => {
+                let t = match self.transf_stack.last() {
+                    Some(t) => t.clone(),
+                    None => TransformLevel::new(),
+                };
 
-                for i in 0..3 {
-                    if let Ok(v_idx) = arg_tokens[i].parse::<usize>() {
-                        new_tri.vertices[i] = scene_info.vertices.0[v_idx];
-                    }
+                self.transf_stack.push(t);
+            },
+            "popTransform" => {
+                // The bottom of the stack is the scene's base transform
+                if self.transf_stack.len() <= 1 {
+                    return Err(cmd.error(cmd.column, SceneErrorKind::TransformStackUnderflow));
                 }
 
-                new_tri.material = *current_material;
+                self.transf_stack.pop();
+            },
+            "keyframe" => {
+                let time = cmd.f64_arg(0)?;
 
-                if let Some(v) = transf_stack.last() {
-                    new_tri.transform = *v;
+                if let Some(t) = self.transf_stack.last_mut() {
+                    t.keyframes.push(Keyframe { time, transform: t.transform });
                 }
+            },
+            "beginCsg" => {
+                let op = cmd.option_arg(0, CSG_OPS, CsgOp::from_name)?;
 
-                scene_info.shapes.0.push(ShapeType::Triangle(new_tri));
-            }
-        },
-        "sphere" => {
-            let mut new_sphere = Sphere::new();
+                self.csg_stack.push(CsgGroup {
+                    line: cmd.line,
+                    column: cmd.column,
+                    csg: Csg::new(op),
+                });
+            },
+            "endCsg" => {
+                let group = match self.csg_stack.pop() {
+                    Some(g) => g,
+                    None => return Err(cmd.error(cmd.column, SceneErrorKind::CsgStackUnderflow)),
+                };
 
-            if arg_tokens.len() >= 4 {
-                for i in 0..3 {
-                    if let Ok(v) = arg_tokens[i].parse::<f64>() {
-                        new_sphere.center[i] = v;
-                    }
+                if group.csg.operands.is_empty() {
+                    return Err(cmd.error(cmd.column, SceneErrorKind::EmptyCsg));
                 }
 
-                if let Ok(v) = arg_tokens[3].parse::<f64>() {
-                    new_sphere.radius = v;
+                // The operands already move on their own
+                self.add_shape(cmd, group.csg)?;
+            },
+            "beginObject" => {
+                if self.object_group.is_some() || !self.csg_stack.is_empty() {
+                    return Err(cmd.error(cmd.column, SceneErrorKind::NestedObject));
                 }
 
-                new_sphere.material = *current_material;
+                let outer_transf_stack = std::mem::replace(&mut self.transf_stack, vec![TransformLevel::new()]);
 
-                if let Some(v) = transf_stack.last() {
-                    new_sphere.transform = *v;
+                self.object_group = Some(ObjectGroup {
+                    line: cmd.line,
+                    column: cmd.column,
+                    name: cmd.args[0].1.to_string(),
+                    shapes: Shapes::new(),
+                    outer_transf_stack,
+                });
+            },
+            "endObject" => {
+                // CSG groups opened inside the object must be ended inside it too
+                if !self.csg_stack.is_empty() {
+                    return Err(cmd.error(cmd.column, SceneErrorKind::UnclosedCsg));
                 }
 
-                scene_info.shapes.0.push(ShapeType::Sphere(new_sphere));
-            }
-        },
-        "scale" => {
-            if arg_tokens.len() >= 3 {
-                if let (Ok(x), Ok(y), Ok(z)) = (arg_tokens[0].parse::<f64>(), arg_tokens[1].parse::<f64>(), arg_tokens[2].parse::<f64>()) {
-                    let trans_mat = Matrix4::new_scale(x, y, z);
-                    right_mul_transf_stack(&trans_mat, transf_stack);
-                }
-            }
-        },
-        "rotate" => {
-            if arg_tokens.len() >= 4 {
-                if let (Ok(x), Ok(y), Ok(z), Ok(r)) = (arg_tokens[0].parse::<f64>(), arg_tokens[1].parse::<f64>(), arg_tokens[2].parse::<f64>(), arg_tokens[3].parse::<f64>()) {
-                    let trans_mat = Matrix4::new_rotate(&Vector3::new(x, y, z), r);
-                    right_mul_transf_stack(&trans_mat, transf_stack);
+                let group = match self.object_group.take() {
+                    Some(g) => g,
+                    None => return Err(cmd.error(cmd.column, SceneErrorKind::ObjectStackUnderflow)),
+                };
+
+                self.transf_stack = group.outer_transf_stack;
+
+                if group.shapes.0.is_empty() {
+                    return Err(cmd.error(cmd.column, SceneErrorKind::EmptyObject));
                 }
-            }
-        },
-        "translate" => {
-            if arg_tokens.len() >= 3 {
-                if let (Ok(x), Ok(y), Ok(z)) = (arg_tokens[0].parse::<f64>(), arg_tokens[1].parse::<f64>(), arg_tokens[2].parse::<f64>()) {
-                    let trans_mat = Matrix4::new_translate(x, y, z);
-                    right_mul_transf_stack(&trans_mat, transf_stack);
+
+                self.objects.insert(group.name.clone(), Arc::new(ObjectDefinition::new(&group.name, group.shapes)));
+            },
+            "instance" => {
+                let (column, name) = cmd.args[0];
+                let object = match self.objects.get(name) {
+                    Some(o) => o.clone(),
+                    None => return Err(cmd.error(column, SceneErrorKind::UnknownObject(name.to_string()))),
+                };
+
+                let mut new_instance = Instance::new(object);
+
+                new_instance.transform = self.current_transform();
+                new_instance.material = self.current_material;
+
+                if cmd.args.len() > 1 {
+                    new_instance.override_material = cmd.option_arg(1, INSTANCE_MATERIALS, |m| match m {
+                        "keep" => Some(false),
+                        "override" => Some(true),
+                        _ => None,
+                    })?;
                 }
-            }
-        },
-        "pushTransform" => {
-            if let Some(t) = transf_stack.last() {
-                transf_stack.push(*t);
-            }
-        },
-        "popTransform" => {
-            if let None = transf_stack.pop() {
-                // No transforms to pop!
-            }
-        },
-        _ => ()
+
+                self.push_shape(cmd, new_instance)?;
+            },
+            _ => return Err(cmd.error(cmd.column, SceneErrorKind::UnknownCommand)),
+        }
+
+        Ok(())
     }
 }
 
-pub fn read_scene_file(file_path_str: &String) -> Scene {
-    let file_path = Path::new(&file_path_str);
-    let mut scene_info = Scene::new();
-    let mut current_material = Material::new();
-    let mut transf_stack = vec![Matrix4::new_on_diag(1.0)];
+// Reads a scene in the text format from any reader, returning it along with the problems
+// skipped over in lenient mode. Meshes and textures named in the scene are looked up
+// relative to base_dir.
+pub fn parse_scene_with_mode<R: BufRead>(reader: R, base_dir: &Path, mode: ParseMode) -> Result <(Scene, Vec <SceneError>), SceneError> {
+    let mut parser = SceneParser::new(mode, base_dir.to_path_buf());
 
-    match read_lines(&file_path_str) {
-        Err(why) => {
-            panic!("Cannot open file {}: {}", file_path.display(), why);
-        },
-        Ok(file_obj) => {
-            for each_line in file_obj {
-                if let Ok(each_line_safe) = each_line {
-                    match_cmd(each_line_safe, &mut transf_stack, &mut current_material, &mut scene_info);
-                }
-            }
+    for (i, each_line) in reader.lines().enumerate() {
+        let result = match each_line {
+            Ok(each_line_safe) => parser.parse_line(i + 1, &each_line_safe),
+            Err(why) => parser.report(Err(SceneError::new(i + 1, 1, "", SceneErrorKind::Io(why)))),
+        };
 
-            return scene_info;
-        }
+        result?;
     }
+
+    while let Some(group) = parser.csg_stack.pop() {
+        let unclosed = Err(SceneError::new(group.line, group.column, "beginCsg", SceneErrorKind::UnclosedCsg));
+
+        parser.report(unclosed)?;
+    }
+
+    if let Some(group) = parser.object_group.take() {
+        let unclosed = Err(SceneError::new(group.line, group.column, "beginObject", SceneErrorKind::UnclosedObject));
+
+        parser.report(unclosed)?;
+    }
+
+    // Keyframes may be given in any order
+    parser.scene.camera_keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let first_frame = parser.scene.frame_range.map_or(0, |r| r.first);
+    parser.scene.set_frame(first_frame);
+
+    // set_frame leaves the BVH alone when nothing moves
+    if !parser.scene.has_moving_shapes() {
+        parser.scene.build_bvh();
+    }
+
+    Ok((parser.scene, parser.warnings))
+}
+
+// Reads a scene from any reader in lenient mode, discarding warnings. Relative paths in
+// the scene are resolved against the working directory.
+pub fn parse_scene<R: BufRead>(reader: R) -> Result <Scene, SceneError> {
+    parse_scene_with_mode(reader, Path::new(""), ParseMode::Lenient).map(|(scene, _)| scene)
+}
+
+// Reads a scene held in a string, such as one embedded in the program
+pub fn parse_scene_str(scene_str: &str) -> Result <Scene, SceneError> {
+    parse_scene(scene_str.as_bytes())
+}
+
+// Reads a scene file, returning it along with the problems skipped over in lenient mode
+pub fn read_scene_file_with_mode(file_path_str: &str, mode: ParseMode) -> Result <(Scene, Vec <SceneError>), SceneError> {
+    let file = match File::open(file_path_str) {
+        Ok(f) => f,
+        Err(why) => return Err(SceneError::new(0, 0, "", SceneErrorKind::Io(why))),
+    };
+
+    let base_dir = match Path::new(file_path_str).parent() {
+        Some(p) => p.to_path_buf(),
+        None => PathBuf::new(),
+    };
+
+    parse_scene_with_mode(io::BufReader::new(file), &base_dir, mode)
+}
+
+// Reads a scene in lenient mode, discarding warnings
+pub fn read_scene_file(file_path_str: &str) -> Result <Scene, SceneError> {
+    read_scene_file_with_mode(file_path_str, ParseMode::Lenient).map(|(scene, _)| scene)
 }
 
 pub fn write_image(file_path_str: &String, image: DynamicImage) {
     if let Err(e) = image.save(file_path_str) {
         panic!("Error occurred saving image to file {}: {}", file_path_str, e);
     }
-}
\ No newline at end of file
+}
+
+impl HdrFormat {
+    // Picks the format from the file extension, None for low dynamic range formats
+    pub fn from_path(file_path_str: &str) -> Option <Self> {
+        let ext = Path::new(file_path_str).extension()?.to_str()?.to_ascii_lowercase();
+
+        match ext.as_str() {
+            "exr" => Some(HdrFormat::OpenExr),
+            "hdr" => Some(HdrFormat::Radiance),
+            "pfm" => Some(HdrFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+fn write_pfm<W: Write>(mut writer: W, framebuffer: &Framebuffer) -> io::Result<()> {
+    // Negative scale marks little-endian data. Rows are stored bottom to top.
+    write!(writer, "PF\n{} {}\n-1.0\n", framebuffer.width, framebuffer.height)?;
+
+    for y in (0..framebuffer.height).rev() {
+        for x in 0..framebuffer.width {
+            let color = framebuffer.get(x, y);
+
+            for i in 0..3 {
+                writer.write_all(&(color[i] as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+pub fn write_hdr_image(file_path_str: &str, framebuffer: &Framebuffer, format: HdrFormat) -> ImageResult<()> {
+    match format {
+        HdrFormat::OpenExr => build_hdr_image(framebuffer).save(file_path_str),
+        HdrFormat::Radiance => {
+            let writer = BufWriter::new(File::create(file_path_str)?);
+            let data: Vec <Rgb <f32>> = framebuffer.pixels.iter().map(|c| Rgb([c[0] as f32, c[1] as f32, c[2] as f32])).collect();
+
+            HdrEncoder::new(writer).encode(&data, framebuffer.width, framebuffer.height)
+        },
+        HdrFormat::Pfm => {
+            let writer = BufWriter::new(File::create(file_path_str)?);
+
+            write_pfm(writer, framebuffer).map_err(ImageError::IoError)
+        },
+    }
+}
+
+// Writes linear data for HDR formats and a tone mapped 8-bit image for everything else,
+// choosing by file extension
+pub fn write_framebuffer(file_path_str: &str, framebuffer: &Framebuffer, tone_mapping: &ToneMapping) -> ImageResult<()> {
+    match HdrFormat::from_path(file_path_str) {
+        Some(format) => write_hdr_image(file_path_str, framebuffer, format),
+        None => build_image(framebuffer, tone_mapping).save(file_path_str),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict_error(scene_str: &str) -> SceneError {
+        match parse_scene_with_mode(scene_str.as_bytes(), Path::new(""), ParseMode::Strict) {
+            Ok(_) => panic!("expected an error parsing {:?}", scene_str),
+            Err(e) => e,
+        }
+    }
+
+    fn lenient_warnings(scene_str: &str) -> (Scene, Vec <SceneError>) {
+        parse_scene_with_mode(scene_str.as_bytes(), Path::new(""), ParseMode::Lenient).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn position(e: &SceneError) -> (usize, usize, &str) {
+        (e.line, e.column, e.command.as_str())
+    }
+
+    #[test]
+    fn unknown_command() {
+        let e = strict_error("size 10 10\n  bogus 1 2\n");
+
+        assert_eq!(position(&e), (2, 3, "bogus"));
+        assert!(matches!(e.kind, SceneErrorKind::UnknownCommand));
+        assert_eq!(e.to_string(), "line 2, column 3 (bogus): unknown command");
+    }
+
+    #[test]
+    fn wrong_argument_count() {
+        let e = strict_error("# comment\nsphere 0 0 0\n");
+
+        assert_eq!(position(&e), (2, 1, "sphere"));
+        assert!(matches!(e.kind, SceneErrorKind::MissingArguments { expected: 4, found: 3 }));
+
+        let e = strict_error("size 10 10 10\n");
+
+        assert_eq!(position(&e), (1, 12, "size"));
+        assert!(matches!(e.kind, SceneErrorKind::ExtraArguments { expected: 2, found: 3 }));
+
+        // Only orthographic projections take a height
+        let e = strict_error("projection fisheye 2\n");
+
+        assert_eq!(position(&e), (1, 20, "projection"));
+        assert!(matches!(e.kind, SceneErrorKind::ExtraArguments { expected: 1, found: 2 }));
+    }
+
+    #[test]
+    fn bad_number() {
+        let e = strict_error("size 10 10\nsphere 0 0 x 1\n");
+
+        assert_eq!(position(&e), (2, 12, "sphere"));
+        assert!(matches!(&e.kind, SceneErrorKind::InvalidNumber(token) if token == "x"));
+    }
+
+    #[test]
+    fn bad_frame_range() {
+        let e = strict_error("frames 4 2\n");
+
+        assert_eq!(position(&e), (1, 10, "frames"));
+        assert!(matches!(e.kind, SceneErrorKind::InvalidValue(_)));
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let e = strict_error("pushTransform\npopTransform\n\tpopTransform\n");
+
+        assert_eq!(position(&e), (3, 2, "popTransform"));
+        assert!(matches!(e.kind, SceneErrorKind::TransformStackUnderflow));
+
+        let e = strict_error("endCsg\n");
+
+        assert_eq!(position(&e), (1, 1, "endCsg"));
+        assert!(matches!(e.kind, SceneErrorKind::CsgStackUnderflow));
+    }
+
+    // Lenient mode reports the same problems, but skips the lines and carries on
+    #[test]
+    fn lenient_mode_warns() {
+        let (scene, warnings) = lenient_warnings("bogus\nsize 10 10 10\nsphere 0 0 x 1\npopTransform\nsphere 0 0 0 1\n");
+        let positions: Vec <(usize, usize, &str)> = warnings.iter().map(position).collect();
+
+        assert_eq!(positions, vec![(1, 1, "bogus"), (2, 12, "size"), (3, 12, "sphere"), (4, 1, "popTransform")]);
+        assert_eq!((scene.img_width, scene.img_height), (10, 10));
+        assert_eq!(scene.shapes.0.len(), 1);
+    }
+}
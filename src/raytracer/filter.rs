@@ -0,0 +1,102 @@
+// Pixel reconstruction filters. Each is separable and centered on the pixel, with
+// radius measured in pixels.
+#[derive(Debug, Clone, Copy)]
+pub enum PixelFilter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, alpha: f64 },
+    Mitchell { radius: f64, b: f64, c: f64 },
+}
+
+impl PixelFilter {
+    pub fn new_box() -> Self {
+        PixelFilter::Box { radius: 0.5 }
+    }
+
+    pub fn new_tent() -> Self {
+        PixelFilter::Tent { radius: 1.0 }
+    }
+
+    pub fn new_gaussian() -> Self {
+        PixelFilter::Gaussian { radius: 1.5, alpha: 2.0 }
+    }
+
+    // Uses the B = C = 1/3 parameters recommended by Mitchell and Netravali
+    pub fn new_mitchell() -> Self {
+        PixelFilter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 }
+    }
+
+    // Filter with its default radius
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "box" => Some(Self::new_box()),
+            "tent" => Some(Self::new_tent()),
+            "gaussian" => Some(Self::new_gaussian()),
+            "mitchell" => Some(Self::new_mitchell()),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelFilter::Box { .. } => "box",
+            PixelFilter::Tent { .. } => "tent",
+            PixelFilter::Gaussian { .. } => "gaussian",
+            PixelFilter::Mitchell { .. } => "mitchell",
+        }
+    }
+
+    pub fn with_radius(self, new_radius: f64) -> Self {
+        match self {
+            PixelFilter::Box { .. } => PixelFilter::Box { radius: new_radius },
+            PixelFilter::Tent { .. } => PixelFilter::Tent { radius: new_radius },
+            PixelFilter::Gaussian { alpha, .. } => PixelFilter::Gaussian { radius: new_radius, alpha },
+            PixelFilter::Mitchell { b, c, .. } => PixelFilter::Mitchell { radius: new_radius, b, c },
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            PixelFilter::Box { radius }
+            | PixelFilter::Tent { radius }
+            | PixelFilter::Gaussian { radius, .. }
+            | PixelFilter::Mitchell { radius, .. } => radius,
+        }
+    }
+
+    // Weight of a sample at offset (dx, dy) from the pixel center
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+
+        match *self {
+            PixelFilter::Box { radius } => {
+                if x <= radius { 1.0 } else { 0.0 }
+            },
+            PixelFilter::Tent { radius } => {
+                (radius - x).max(0.0)
+            },
+            PixelFilter::Gaussian { radius, alpha } => {
+                // Shifted down so that the weight reaches zero at the radius
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            },
+            PixelFilter::Mitchell { radius, b, c } => {
+                // The Mitchell-Netravali kernel is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+                }
+                else if x < 2.0 {
+                    ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                }
+                else {
+                    0.0
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::geometry::RGBColor;
+
+// Linear, unclamped radiance per pixel, stored row by row from the top
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec <RGBColor>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![RGBColor::new_empty(); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> RGBColor {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: RGBColor) {
+        self.pixels[y * self.width + x] = color;
+    }
+}
@@ -0,0 +1,75 @@
+use crate::primitives::Ray;
+use crate::geometry::RGBColor;
+use super::{Scene, Rng, intersect_scene_from_view, get_color_recursive, trace_path};
+
+// Rendering algorithm computing the color seen along camera rays. One integrator is
+// shared by all render threads.
+pub trait Integrator: Sync {
+    // rng is seeded per pixel, so integrators drawing from it render reproducibly
+    fn radiance(&self, scene: &Scene, ray: Ray, rng: &mut Rng) -> RGBColor;
+}
+
+// Integrators that can be selected from a scene file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegratorKind {
+    Whitted,
+    PathTracing,
+}
+
+// Phong shading with recursive mirror reflection and refraction
+pub struct WhittedIntegrator;
+
+// Monte Carlo path tracing, see trace_path
+pub struct PathIntegrator;
+
+impl IntegratorKind {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "whitted" => Some(IntegratorKind::Whitted),
+            "path" => Some(IntegratorKind::PathTracing),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegratorKind::Whitted => "whitted",
+            IntegratorKind::PathTracing => "path",
+        }
+    }
+
+    pub fn integrator(&self) -> &'static dyn Integrator {
+        match self {
+            IntegratorKind::Whitted => &WhittedIntegrator,
+            IntegratorKind::PathTracing => &PathIntegrator,
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, scene: &Scene, ray: Ray, _rng: &mut Rng) -> RGBColor {
+        let emitter = scene.lights.intersect_emitters(&ray);
+
+        // Intersection test with scene
+        if let Some(id) = intersect_scene_from_view(ray, scene) {
+            match emitter {
+                Some((dist, color)) if dist < (id.coords - &ray.position).len() => color,
+                // Use get_color_recursive to get reflections
+                _ => get_color_recursive(ray, scene, id, 0),
+            }
+        }
+        else if let Some((_, color)) = emitter {
+            color
+        }
+        else {
+            // Color all pixels black
+            RGBColor::new_empty()
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, scene: &Scene, ray: Ray, rng: &mut Rng) -> RGBColor {
+        trace_path(scene, ray, rng)
+    }
+}
@@ -1,62 +1,177 @@
+use std::f64::consts::PI;
+
 use crate::primitives::*;
 use super::data::*;
 use crate::geometry::*;
 
-pub trait Intersectable {
-    fn intersect(&self, ray: &Ray) -> Option <IntersectData>;
+// Rays are considered to miss everything beyond this distance
+pub const MAX_RAY_DIST: f64 = 1000000.0;
+
+// Distance rays leaving a surface by intersect_scene_from_surface start away from it
+const SURFACE_OFFSET: f64 = 1e-6;
+
+// Texture coordinates for triangles that don't specify any
+const BARYCENTRIC_UVS: [(f64, f64); 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+// Barycentric coordinates of a point in the triangle's plane
+fn barycentric(vertices: &[Point3; 3], point: &Point3) -> [f64; 3] {
+    let [a, b, c] = *vertices;
+    let tri_norm = (b - &a).cross(&(c - &a));
+
+    let norm_a = (c - &b).cross(&(*point - &b));
+    let norm_b = (a - &c).cross(&(*point - &c));
+
+    let alpha = tri_norm.dot(&norm_a) / tri_norm.dot(&tri_norm);
+    let beta = tri_norm.dot(&norm_b) / tri_norm.dot(&tri_norm);
+
+    [alpha, beta, 1.0 - alpha - beta]
 }
 
-impl Intersectable for Triangle {
-    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
-        let ray_trans = self.transform.mat_invtf_ray(&ray);
-        let [a, b, c] = self.vertices; // Assignment for convenience
-        let tri_norm = (b - &a).cross(&(c - &a));
-        let tri_norm_u1 = tri_norm.norm();
-        let divisor = ray_trans.direction.dot(&tri_norm_u1);
-
-        // Check intersection point
-        if ((divisor * 1000000.0).abs() as isize) < 1 {
-            return None;
-        }
+// Object-space ray parameter, point and barycentric coordinates where the ray hits the triangle
+fn intersect_triangle(ray_trans: &Ray, vertices: &[Point3; 3]) -> Option <(f64, Point3, [f64; 3])> {
+    let [a, b, c] = *vertices; // Assignment for convenience
+    let tri_norm = (b - &a).cross(&(c - &a));
+    let tri_norm_u1 = tri_norm.norm();
+    let divisor = ray_trans.direction.dot(&tri_norm_u1);
 
-        let intersect = ((a - &ray_trans.position).dot(&tri_norm_u1)) / divisor;
+    // Check intersection point
+    if ((divisor * 1000000.0).abs() as isize) < 1 {
+        return None;
+    }
 
-        if intersect < 1e-7 {
-            return None;
-        }
+    let intersect = ((a - &ray_trans.position).dot(&tri_norm_u1)) / divisor;
 
-        let intersect_pt = ray_trans.position + &(ray_trans.direction * intersect);
-
-        // Check barycentric coordinates. They're always positive
-        let norm_a = (c - &b).cross(&(intersect_pt - &b));
-        let norm_b = (a - &c).cross(&(intersect_pt - &c));
-
-        let alpha = tri_norm.dot(&norm_a) / tri_norm.dot(&tri_norm);
-        let beta = tri_norm.dot(&norm_b) / tri_norm.dot(&tri_norm);
-        let gamma = 1.0 - alpha - beta;
-        
-        let a_eps = (alpha * 1000000.0) as isize;
-        let b_eps = (beta * 1000000.0) as isize;
-        let g_eps = (gamma * 1000000.0) as isize;
-
-        // Check if coordinates satisfy 0 <= a <= 1
-        if alpha <= 1.0 && beta <= 1.0 && gamma <= 1.0
-            && a_eps >= 0 && b_eps >= 0 && g_eps >= 0 {
-            Some(IntersectData {
-                index: 0,
-                coords: self.transform * &intersect_pt,
-                shape: ShapeType::Triangle(*self),
-            })
-        }
-        else {
-            None
-        }
+    if intersect < 1e-7 {
+        return None;
+    }
+
+    let intersect_pt = ray_trans.position + &(ray_trans.direction * intersect);
+
+    // Check barycentric coordinates. They're always positive
+    let [alpha, beta, gamma] = barycentric(vertices, &intersect_pt);
+
+    let a_eps = (alpha * 1000000.0) as isize;
+    let b_eps = (beta * 1000000.0) as isize;
+    let g_eps = (gamma * 1000000.0) as isize;
+
+    // Check if coordinates satisfy 0 <= a <= 1
+    if alpha <= 1.0 && beta <= 1.0 && gamma <= 1.0
+        && a_eps >= 0 && b_eps >= 0 && g_eps >= 0 {
+        Some((intersect, intersect_pt, [alpha, beta, gamma]))
     }
+    else {
+        None
+    }
+}
+
+fn triangle_normal(vertices: &[Point3; 3]) -> Vector3 {
+    (vertices[1] - &vertices[0]).cross(&(vertices[2] - &vertices[0])).norm()
+}
+
+fn interpolate_normal(normals: &[Vector3; 3], bary: &[f64; 3]) -> Vector3 {
+    (normals[0] * bary[0] + &(normals[1] * bary[1]) + &(normals[2] * bary[2])).norm()
+}
+
+fn interpolate_uv(uvs: &[(f64, f64); 3], bary: &[f64; 3]) -> (f64, f64) {
+    (
+        uvs[0].0 * bary[0] + uvs[1].0 * bary[1] + uvs[2].0 * bary[2],
+        uvs[0].1 * bary[0] + uvs[1].1 * bary[1] + uvs[2].1 * bary[2],
+    )
+}
+
+// Ratio of the triangle's extent in UV space to its extent in world space
+fn triangle_uv_density(world_vertices: &[Point3; 3], uvs: &[(f64, f64); 3]) -> f64 {
+    let world_area = (world_vertices[1] - &world_vertices[0]).cross(&(world_vertices[2] - &world_vertices[0])).len();
+    let uv_area = ((uvs[1].0 - uvs[0].0) * (uvs[2].1 - uvs[0].1) - (uvs[2].0 - uvs[0].0) * (uvs[1].1 - uvs[0].1)).abs();
+
+    if world_area > 0.0 {
+        (uv_area / world_area).sqrt()
+    }
+    else {
+        0.0
+    }
+}
+
+fn triangle_area(vertices: &[Point3; 3]) -> f64 {
+    0.5 * (vertices[1] - &vertices[0]).cross(&(vertices[2] - &vertices[0])).len()
+}
+
+// Point spread evenly over the triangle as u covers the unit square
+fn sample_triangle(vertices: &[Point3; 3], u: (f64, f64)) -> Point3 {
+    let su = u.0.sqrt();
+    let (b1, b2) = (su * (1.0 - u.1), su * u.1);
+    let [a, b, c] = *vertices;
+
+    a + &((b - &a) * b1) + &((c - &a) * b2)
 }
 
-impl Intersectable for Sphere {
+impl Shape for Triangle {
     fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
-        let ray_trans = self.transform.mat_invtf_ray(&ray);
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let (_, intersect_pt, bary) = intersect_triangle(&ray_trans, &self.vertices)?;
+        let coords = self.transform * &intersect_pt;
+        let geom_normal = self.normal_at(&coords, 0);
+        let uvs = self.uvs.unwrap_or(BARYCENTRIC_UVS);
+
+        Some(IntersectData {
+            index: 0,
+            time: 0.0,
+            face: 0,
+            coords,
+            normal: match &self.normals {
+                Some(n) => self.transform.mat_invtf_norm_vec3(&interpolate_normal(n, &bary)),
+                None => geom_normal,
+            },
+            geom_normal,
+            uv: interpolate_uv(&uvs, &bary),
+            uv_density: if self.material.textures.is_empty() {
+                0.0
+            }
+            else {
+                triangle_uv_density(&self.vertices.map(|v| self.transform * &v), &uvs)
+            },
+            material: self.material,
+        })
+    }
+
+    fn normal_at(&self, _point: &Point3, _face: usize) -> Vector3 {
+        self.transform.mat_invtf_norm_vec3(&triangle_normal(&self.vertices))
+    }
+
+    fn uv_at(&self, point: &Point3, _face: usize) -> (f64, f64) {
+        let bary = barycentric(&self.vertices, &self.transform.mat_invtf_point3(point));
+
+        interpolate_uv(&self.uvs.unwrap_or(BARYCENTRIC_UVS), &bary)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let world_vertices = self.vertices.map(|v| self.transform * &v);
+
+        BoundingBox::from_points(&world_vertices)
+    }
+
+    fn area(&self) -> f64 {
+        triangle_area(&self.vertices.map(|v| self.transform * &v))
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let point = sample_triangle(&self.vertices.map(|v| self.transform * &v), u);
+
+        (point, self.normal_at(&point, 0))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Triangle(self))
+    }
+}
+
+impl Sphere {
+    // Both ray parameters where the ray's line crosses the sphere, smallest first
+    fn object_roots(&self, ray_trans: &Ray) -> Option <(f64, f64)> {
         let center_pos = ray_trans.position - &self.center;
 
         let a = ray_trans.direction.dot(&ray_trans.direction);
@@ -71,107 +186,283 @@ impl Intersectable for Sphere {
         let t1 = (-b - discriminant.sqrt()) / (a * 2.0);
         let t2 = (-b + discriminant.sqrt()) / (a * 2.0);
 
+        Some((t1.min(t2), t1.max(t2)))
+    }
+
+    fn hit_at(&self, ray_trans: &Ray, t: f64) -> IntersectData {
+        let coords = self.transform * &(ray_trans.position + &(ray_trans.direction * t));
+        let normal = self.normal_at(&coords, 0);
+        let world_radius = (coords - &(self.transform * &self.center)).len();
+
+        IntersectData {
+            index: 0,
+            time: 0.0,
+            face: 0,
+            coords,
+            normal,
+            geom_normal: normal,
+            uv: self.uv_at(&coords, 0),
+            // The whole unit UV square covers the sphere's surface area
+            uv_density: 1.0 / (2.0 * world_radius * PI.sqrt()),
+            material: self.material,
+        }
+    }
+}
+
+impl Shape for Sphere {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let (t1, t2) = self.object_roots(&ray_trans)?;
+
         if t2 < 0.0 {
             return None;
         }
 
-        let inter_t = if t1 < 0.0 || t2 < t1 {
-            t2
+        let inter_t = if t1 < 0.0 { t2 } else { t1 };
+
+        Some(self.hit_at(&ray_trans, inter_t))
+    }
+
+    fn normal_at(&self, point: &Point3, _face: usize) -> Vector3 {
+        // Get intersection point in object space before finding the normal
+        let intersect_obj_space = self.transform.mat_invtf_point3(point);
+
+        self.transform.mat_invtf_norm_vec3(&(intersect_obj_space - &self.center).norm())
+    }
+
+    // Longitude and latitude in object space, with the poles on the y axis
+    fn uv_at(&self, point: &Point3, _face: usize) -> (f64, f64) {
+        let local = self.transform.mat_invtf_point3(point) - &self.center;
+
+        (
+            0.5 + local[2].atan2(local[0]) / (2.0 * PI),
+            0.5 + (local[1] / self.radius).clamp(-1.0, 1.0).asin() / PI,
+        )
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let radius = Vector3::new_with_value(self.radius);
+        let obj_bounds = BoundingBox::new(self.center - &radius, self.center + &radius);
+
+        obj_bounds.transform(&self.transform)
+    }
+
+    // Exact when the transform scales evenly in all directions
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius * self.transform.determinant3().abs().powf(2.0 / 3.0)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let dir = Vector3::new(r * (2.0 * PI * u.1).cos(), r * (2.0 * PI * u.1).sin(), z);
+        let point = self.transform * &(self.center + &(dir * self.radius));
+
+        (point, self.normal_at(&point, 0))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn solid_spans(&self, ray: &Ray) -> Vec <SolidSpan> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+
+        match self.object_roots(&ray_trans) {
+            Some((t1, t2)) => vec![SolidSpan {
+                entry: SurfaceCrossing { t: t1, hit: self.hit_at(&ray_trans, t1) },
+                exit: SurfaceCrossing { t: t2, hit: self.hit_at(&ray_trans, t2) },
+            }],
+            None => Vec::new(),
         }
-        else {
-            t1
-        };
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Sphere(self))
+    }
+}
+
+impl Mesh {
+    fn intersect_faces(&self, ray: &Ray, skip_face: Option <usize>) -> Option <IntersectData> {
+        let ray_trans = self.transform.mat_invtf_ray(ray);
+        let dir_len = ray_trans.direction.len();
+
+        // The mesh BVH works in object space, so distances are object-space too
+        let (_, (face, intersect_pt, bary)) = self.mesh.bvh().intersect_nearest(&ray_trans, f64::INFINITY, |f, _| {
+            if skip_face == Some(f) {
+                return None;
+            }
+
+            intersect_triangle(&ray_trans, &self.mesh.face_vertices(f)).map(|(t, pt, bary)| (t * dir_len, (f, pt, bary)))
+        })?;
+
+        let coords = self.transform * &intersect_pt;
+        let geom_normal = self.normal_at(&coords, face);
+        let uvs = self.mesh.face_uvs(face).unwrap_or(BARYCENTRIC_UVS);
 
         Some(IntersectData {
             index: 0,
-            coords: self.transform * &(ray_trans.position + &(ray_trans.direction * inter_t)),
-            shape: ShapeType::Sphere(*self),
+            time: 0.0,
+            face,
+            coords,
+            normal: match self.mesh.face_normals(face) {
+                Some(n) => self.transform.mat_invtf_norm_vec3(&interpolate_normal(&n, &bary)),
+                None => geom_normal,
+            },
+            geom_normal,
+            uv: interpolate_uv(&uvs, &bary),
+            uv_density: if self.material.textures.is_empty() {
+                0.0
+            }
+            else {
+                triangle_uv_density(&self.world_face_vertices(face), &uvs)
+            },
+            material: self.material,
         })
     }
+
+    fn world_face_vertices(&self, face: usize) -> [Point3; 3] {
+        self.mesh.face_vertices(face).map(|v| self.transform * &v)
+    }
 }
 
-pub fn intersect_scene_from_view(ray: Ray, scene: &Scene) -> Option <IntersectData> {
-    let mut min_dist = 1000000.0; // Let initial minimum distance be at infinity (10^6)
-    let mut nearest_shape = IntersectData::new();
-
-    // Iterate through each object and check lesser t
-    for (i, each_shape) in scene.shapes.0.iter().enumerate() {
-        match each_shape {
-            ShapeType::Sphere(s) => {
-                if let Some(mut intersect_data) = s.intersect(&ray) {
-                    let vec_dist = (ray.position - &intersect_data.coords).len();
-
-                    intersect_data.index = i;
-
-                    if vec_dist < min_dist {
-                        min_dist = vec_dist;
-                        nearest_shape = intersect_data;
-                    }
-                }
-            },
-            ShapeType::Triangle(t) => {
-                if let Some(mut intersect_data) = t.intersect(&ray) {
-                    let vec_dist = (ray.position - &intersect_data.coords).len();
+impl Shape for Mesh {
+    fn intersect(&self, ray: &Ray) -> Option <IntersectData> {
+        self.intersect_faces(ray, None)
+    }
 
-                    intersect_data.index = i;
+    fn intersect_from_face(&self, ray: &Ray, face: usize) -> Option <IntersectData> {
+        self.intersect_faces(ray, Some(face))
+    }
 
-                    if vec_dist < min_dist {
-                        min_dist = vec_dist;
-                        nearest_shape = intersect_data;
-                    }
-                }
-            },
-            _ => ()
-        }
+    fn normal_at(&self, _point: &Point3, face: usize) -> Vector3 {
+        self.transform.mat_invtf_norm_vec3(&triangle_normal(&self.mesh.face_vertices(face)))
     }
 
-    if min_dist < 1000000.0 {
-        Some(nearest_shape)
+    fn uv_at(&self, point: &Point3, face: usize) -> (f64, f64) {
+        let bary = barycentric(&self.mesh.face_vertices(face), &self.transform.mat_invtf_point3(point));
+
+        interpolate_uv(&self.mesh.face_uvs(face).unwrap_or(BARYCENTRIC_UVS), &bary)
     }
-    else {
-        None
+
+    fn bounds(&self) -> BoundingBox {
+        self.mesh.bounds().transform(&self.transform)
+    }
+
+    fn area(&self) -> f64 {
+        (0..self.mesh.faces.len()).map(|f| triangle_area(&self.world_face_vertices(f))).sum()
+    }
+
+    // Faces are picked by object-space area, which is exact when the transform scales
+    // evenly in all directions
+    fn sample(&self, u: (f64, f64)) -> (Point3, Vector3) {
+        let (face, u_face) = self.mesh.pick_face(u.0);
+        let point = sample_triangle(&self.world_face_vertices(face), (u_face, u.1));
+
+        (point, self.normal_at(&point, face))
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn describe(&self) -> Option <ShapeDescription<'_>> {
+        Some(ShapeDescription::Mesh(self))
+    }
+}
+
+fn intersect_shape(ray: &Ray, scene: &Scene, i: usize, origin: Option <&IntersectData>) -> Option <IntersectData> {
+    match origin {
+        Some(o) if o.index == i => scene.shapes.0[i].intersect_from_face(ray, o.face),
+        _ => scene.shapes.0[i].intersect(ray),
+    }
+}
+
+// Nearest hit for a ray, which must not hit the face it leaves from again. Falls back
+// to testing every shape when the scene's BVH was not built for its current shape list.
+fn intersect_scene(ray: Ray, scene: &Scene, origin: Option <&IntersectData>) -> Option <IntersectData> {
+    let test_shape = |i: usize, _: f64| {
+        intersect_shape(&ray, scene, i, origin).map(|mut intersect_data| {
+            intersect_data.index = i;
+            intersect_data.time = ray.time;
+            ((ray.position - &intersect_data.coords).len(), intersect_data)
+        })
+    };
+
+    let nearest = if scene.bvh.is_built_for(scene.shapes.0.len()) {
+        scene.bvh.intersect_nearest(&ray, MAX_RAY_DIST, test_shape)
     }
+    else {
+        (0..scene.shapes.0.len())
+            .filter_map(|i| test_shape(i, MAX_RAY_DIST))
+            .filter(|(dist, _)| *dist < MAX_RAY_DIST)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    };
+
+    nearest.map(|(dist, mut intersect_data)| {
+        if !intersect_data.material.textures.is_empty() {
+            // Width of the cone of rays through one sample where it meets the surface,
+            // widened where the surface is seen at a grazing angle
+            let cos_angle = ray.direction.norm().dot(&intersect_data.geom_normal).abs().max(1e-3);
+            let footprint = dist * scene.sample_spread_angle() * intersect_data.uv_density / cos_angle;
+
+            intersect_data.material = intersect_data.material.apply_textures(&scene.textures, intersect_data.uv, footprint);
+        }
+
+        intersect_data
+    })
+}
+
+pub fn intersect_scene_from_view(ray: Ray, scene: &Scene) -> Option <IntersectData> {
+    intersect_scene(ray, scene, None)
 }
 
 pub fn intersect_scene_from_shape(ray: Ray, scene: &Scene, origin: IntersectData) -> Option <IntersectData> {
-    let mut min_dist = 1000000.0; // Let initial minimum distance be at infinity (10^6)
-    let mut nearest_shape = IntersectData::new();
-
-    // Iterate through each object and check lesser t
-    for (i, each_shape) in scene.shapes.0.iter().enumerate() {
-        match each_shape {
-            ShapeType::Sphere(s) => {
-                if let Some(mut intersect_data) = s.intersect(&ray) {
-                    let vec_dist = (ray.position - &intersect_data.coords).len();
-
-                    intersect_data.index = i;
-
-                    if matches!(origin.shape, ShapeType::Sphere(_)) && origin.index != intersect_data.index && vec_dist < min_dist {
-                        min_dist = vec_dist;
-                        nearest_shape = intersect_data;
-                    }
-                }
-            },
-            ShapeType::Triangle(t) => {
-                if let Some(mut intersect_data) = t.intersect(&ray) {
-                    let vec_dist = (ray.position - &intersect_data.coords).len();
+    intersect_scene(ray, scene, Some(&origin))
+}
 
-                    intersect_data.index = i;
+// Nearest hit for a ray leaving the origin's surface to the side its direction points
+// to. Unlike intersect_scene_from_shape the origin's own shape can be hit again, as
+// needed for rays travelling through the inside of a closed shape, so the ray is
+// started slightly off the surface instead.
+pub fn intersect_scene_from_surface(ray: Ray, scene: &Scene, origin: IntersectData) -> Option <IntersectData> {
+    let side = if ray.direction.dot(&origin.geom_normal) < 0.0 { -1.0 } else { 1.0 };
+    let offset_ray = Ray {
+        position: origin.coords + &(origin.geom_normal * (side * SURFACE_OFFSET)),
+        direction: ray.direction,
+        time: ray.time,
+    };
 
-                    if matches!(origin.shape, ShapeType::Triangle(_)) && origin.index != intersect_data.index && vec_dist < min_dist {
-                        min_dist = vec_dist;
-                        nearest_shape = intersect_data;
-                    }
-                }
-            },
-            _ => ()
+    intersect_scene(offset_ray, scene, None)
+}
+
+// Checks whether anything other than the origin's face blocks the segment from the
+// origin's intersection point to target
+pub fn occluded_from_shape(scene: &Scene, origin: IntersectData, target: Point3) -> bool {
+    let ray = Ray {
+        position: origin.coords,
+        direction: (target - &origin.coords).norm(),
+        time: origin.time,
+    };
+    let target_dist_sq = (target - &ray.position).dot(&(target - &ray.position));
+
+    let blocks_ray = |i: usize| {
+        match intersect_shape(&ray, scene, i, Some(&origin)) {
+            // Surfaces beyond the target do not block it
+            Some(intersect_data) => (intersect_data.coords - &ray.position).dot(&(intersect_data.coords - &ray.position)) <= target_dist_sq,
+            None => false,
         }
-    }
+    };
 
-    if min_dist < 1000000.0 {
-        Some(nearest_shape)
+    if scene.bvh.is_built_for(scene.shapes.0.len()) {
+        // Padded so that the box test never culls a hit the exact check above accepts
+        scene.bvh.intersect_any(&ray, target_dist_sq.sqrt() * (1.0 + 1e-9), blocks_ray)
     }
     else {
-        None
+        (0..scene.shapes.0.len()).any(blocks_ray)
     }
-}
\ No newline at end of file
+}
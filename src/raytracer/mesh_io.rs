@@ -0,0 +1,644 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::primitives::*;
+use crate::geometry::*;
+
+fn invalid_data(line: usize, msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+fn parse_f64(line: usize, token: Option <&str>) -> io::Result<f64> {
+    match token {
+        Some(t) => t.parse::<f64>().map_err(|_| invalid_data(line, format!("invalid number \"{}\"", t))),
+        None => Err(invalid_data(line, "missing value".to_string())),
+    }
+}
+
+// OBJ indices are 1-based, negative ones count back from the latest element
+fn obj_index(line: usize, token: &str, count: usize) -> io::Result<usize> {
+    let idx = token.parse::<i64>().map_err(|_| invalid_data(line, format!("invalid index \"{}\"", token)))?;
+
+    let resolved = if idx < 0 { (count as i64) + idx } else { idx - 1 };
+
+    if resolved < 0 || resolved >= (count as i64) {
+        return Err(invalid_data(line, format!("index {} out of range", idx)));
+    }
+
+    Ok(resolved as usize)
+}
+
+// Splits polygons into triangle fans around their first corner, so they are assumed convex
+fn triangulate<T: Copy>(corners: &[T]) -> Vec <[T; 3]> {
+    (1..(corners.len() - 1)).map(|i| [corners[0], corners[i], corners[i + 1]]).collect()
+}
+
+// Reads positions, normals, texture coordinates and faces from Wavefront OBJ text.
+// Other statements (groups, materials, lines, ...) are ignored.
+pub fn read_obj<R: BufRead>(reader: R) -> io::Result<TriangleMesh> {
+    let mut positions: Vec <Point3> = Vec::new();
+    let mut normals: Vec <Vector3> = Vec::new();
+    let mut uvs: Vec <(f64, f64)> = Vec::new();
+    let mut faces: Vec <MeshFace> = Vec::new();
+
+    for (i, each_line) in reader.lines().enumerate() {
+        let line = i + 1;
+        let each_line = each_line?;
+        let mut tokens = each_line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => {
+                positions.push(Point3::new(parse_f64(line, tokens.next())?, parse_f64(line, tokens.next())?, parse_f64(line, tokens.next())?));
+            },
+            Some("vn") => {
+                normals.push(Vector3::new(parse_f64(line, tokens.next())?, parse_f64(line, tokens.next())?, parse_f64(line, tokens.next())?));
+            },
+            Some("vt") => {
+                let u = parse_f64(line, tokens.next())?;
+                let v = match tokens.next() {
+                    Some(t) => parse_f64(line, Some(t))?,
+                    None => 0.0,
+                };
+
+                uvs.push((u, v));
+            },
+            Some("f") => {
+                // Each corner is v, v/vt, v//vn or v/vt/vn
+                let mut corners: Vec <(usize, Option <usize>, Option <usize>)> = Vec::new();
+
+                for corner in tokens {
+                    let mut parts = corner.split('/');
+
+                    let v = obj_index(line, parts.next().unwrap_or(""), positions.len())?;
+                    let vt = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(obj_index(line, t, uvs.len())?),
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(obj_index(line, t, normals.len())?),
+                        _ => None,
+                    };
+
+                    corners.push((v, vt, vn));
+                }
+
+                if corners.len() < 3 {
+                    return Err(invalid_data(line, format!("face with {} corners", corners.len())));
+                }
+
+                for tri in triangulate(&corners) {
+                    let mut face = MeshFace::new(tri.map(|c| c.0));
+
+                    // Attributes are only kept if every corner has them
+                    if tri.iter().all(|c| c.1.is_some()) {
+                        face.uvs = Some(tri.map(|c| c.1.unwrap_or(0)));
+                    }
+
+                    if tri.iter().all(|c| c.2.is_some()) {
+                        face.normals = Some(tri.map(|c| c.2.unwrap_or(0)));
+                    }
+
+                    faces.push(face);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(TriangleMesh::new(positions, normals, uvs, faces))
+}
+
+pub fn read_obj_file<P: AsRef<Path>>(file_path: P) -> io::Result<TriangleMesh> {
+    read_obj(io::BufReader::new(fs::File::open(file_path)?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Debug, Clone)]
+enum PlyProperty {
+    Scalar { name: String, ty: PlyScalar },
+    List { name: String, count_ty: PlyScalar, item_ty: PlyScalar },
+}
+
+#[derive(Debug, Clone)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec <PlyProperty>,
+}
+
+// Reads values from the body of a PLY file, whatever its encoding
+struct PlyBody<'a> {
+    format: PlyFormat,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl PlyScalar {
+    fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "char" | "int8" => Some(PlyScalar::I8),
+            "uchar" | "uint8" => Some(PlyScalar::U8),
+            "short" | "int16" => Some(PlyScalar::I16),
+            "ushort" | "uint16" => Some(PlyScalar::U16),
+            "int" | "int32" => Some(PlyScalar::I32),
+            "uint" | "uint32" => Some(PlyScalar::U32),
+            "float" | "float32" => Some(PlyScalar::F32),
+            "double" | "float64" => Some(PlyScalar::F64),
+            _ => None,
+        }
+    }
+
+    // Bytes a binary value takes. ASCII values take at least one byte each.
+    fn size(&self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+}
+
+impl PlyProperty {
+    fn name(&self) -> &str {
+        match self {
+            PlyProperty::Scalar { name, .. } | PlyProperty::List { name, .. } => name,
+        }
+    }
+}
+
+impl<'a> PlyBody<'a> {
+    fn ends_early() -> io::Error {
+        io::Error::new(io::ErrorKind::UnexpectedEof, "PLY body ends early")
+    }
+
+    // Least number of bytes a value of the type takes up
+    fn min_size(&self, ty: PlyScalar) -> usize {
+        if self.format == PlyFormat::Ascii { 1 } else { ty.size() }
+    }
+
+    // Fails unless what's left of the body can hold count values of at least size bytes
+    // each, so that counts from the file can't make us loop far past its end
+    fn expect_room(&self, count: usize, size: usize) -> io::Result<()> {
+        match count.checked_mul(size) {
+            Some(needed) if needed <= self.data.len() - self.pos => Ok(()),
+            _ => Err(Self::ends_early()),
+        }
+    }
+
+    fn next_token(&mut self) -> io::Result<&'a str> {
+        while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+
+        let start = self.pos;
+
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return Err(Self::ends_early());
+        }
+
+        std::str::from_utf8(&self.data[start..self.pos]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn next_bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.pos + N > self.data.len() {
+            return Err(Self::ends_early());
+        }
+
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(&self.data[self.pos..(self.pos + N)]);
+        self.pos += N;
+
+        if self.format == PlyFormat::BinaryBigEndian {
+            bytes.reverse();
+        }
+
+        Ok(bytes)
+    }
+
+    fn read(&mut self, ty: PlyScalar) -> io::Result<f64> {
+        if self.format == PlyFormat::Ascii {
+            let token = self.next_token()?;
+
+            return token.parse::<f64>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid PLY value \"{}\"", token)));
+        }
+
+        // Bytes are little-endian at this point
+        Ok(match ty {
+            PlyScalar::I8 => i8::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::U8 => u8::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::I16 => i16::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::U16 => u16::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::I32 => i32::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::U32 => u32::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::F32 => f32::from_le_bytes(self.next_bytes()?) as f64,
+            PlyScalar::F64 => f64::from_le_bytes(self.next_bytes()?),
+        })
+    }
+
+    fn skip(&mut self, ty: PlyScalar) -> io::Result<()> {
+        if self.format == PlyFormat::Ascii {
+            self.next_token()?;
+        }
+        else {
+            self.expect_room(1, ty.size())?;
+            self.pos += ty.size();
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_ply_header(data: &[u8]) -> io::Result<(PlyFormat, Vec <PlyElement>, usize)> {
+    let bad_header = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("PLY header: {}", msg));
+
+    let mut format: Option <PlyFormat> = None;
+    let mut elements: Vec <PlyElement> = Vec::new();
+    let mut pos = 0;
+
+    for (i, each_line) in data.split(|&b| b == b'\n').enumerate() {
+        pos += each_line.len() + 1;
+
+        let each_line = String::from_utf8_lossy(each_line);
+        let tokens: Vec <&str> = each_line.split_whitespace().collect();
+
+        if i == 0 {
+            if tokens.first() != Some(&"ply") {
+                return Err(bad_header("missing \"ply\" magic".to_string()));
+            }
+
+            continue;
+        }
+
+        match tokens.as_slice() {
+            ["format", f, _] => {
+                format = Some(match *f {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(bad_header(format!("unknown format \"{}\"", f))),
+                });
+            },
+            ["element", name, count] => {
+                elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse::<usize>().map_err(|_| bad_header(format!("invalid element count \"{}\"", count)))?,
+                    properties: Vec::new(),
+                });
+            },
+            ["property", "list", count_ty, item_ty, name] => {
+                let property = PlyProperty::List {
+                    name: name.to_string(),
+                    count_ty: PlyScalar::from_name(count_ty).ok_or_else(|| bad_header(format!("unknown type \"{}\"", count_ty)))?,
+                    item_ty: PlyScalar::from_name(item_ty).ok_or_else(|| bad_header(format!("unknown type \"{}\"", item_ty)))?,
+                };
+
+                match elements.last_mut() {
+                    Some(e) => e.properties.push(property),
+                    None => return Err(bad_header("property before any element".to_string())),
+                }
+            },
+            ["property", ty, name] => {
+                let property = PlyProperty::Scalar {
+                    name: name.to_string(),
+                    ty: PlyScalar::from_name(ty).ok_or_else(|| bad_header(format!("unknown type \"{}\"", ty)))?,
+                };
+
+                match elements.last_mut() {
+                    Some(e) => e.properties.push(property),
+                    None => return Err(bad_header("property before any element".to_string())),
+                }
+            },
+            ["end_header"] => {
+                return match format {
+                    Some(f) => Ok((f, elements, pos)),
+                    None => Err(bad_header("missing format".to_string())),
+                };
+            },
+            _ => (),
+        }
+    }
+
+    Err(bad_header("missing end_header".to_string()))
+}
+
+// Reads vertex positions, normals (nx, ny, nz), texture coordinates (u, v or s, t) and
+// polygon faces from the contents of an ASCII or binary PLY file
+pub fn read_ply(data: &[u8]) -> io::Result<TriangleMesh> {
+    let (format, elements, body_start) = parse_ply_header(data)?;
+    let mut body = PlyBody { format, data: &data[body_start..], pos: 0 };
+
+    if !elements.iter().any(|e| e.name == "vertex" && ["x", "y", "z"].iter().all(|n| e.properties.iter().any(|p| p.name() == *n))) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "PLY file has no vertex positions"));
+    }
+
+    let mut positions: Vec <Point3> = Vec::new();
+    let mut normals: Vec <Vector3> = Vec::new();
+    let mut uvs: Vec <(f64, f64)> = Vec::new();
+    let mut polygons: Vec <Vec <usize>> = Vec::new();
+
+    for element in &elements {
+        // Elements without properties take up no room in the body, so are skipped
+        // without going through their count
+        if element.properties.is_empty() {
+            continue;
+        }
+
+        let min_element_size: usize = element.properties.iter().map(|p| match p {
+            PlyProperty::Scalar { ty, .. } => body.min_size(*ty),
+            PlyProperty::List { count_ty, .. } => body.min_size(*count_ty),
+        }).sum();
+
+        body.expect_room(element.count, min_element_size)?;
+
+        for _ in 0..element.count {
+            let mut values = [0.0; 8];
+            let mut found = [false; 8];
+
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar { name, ty } => {
+                        let slot = match (element.name.as_str(), name.as_str()) {
+                            ("vertex", "x") => Some(0),
+                            ("vertex", "y") => Some(1),
+                            ("vertex", "z") => Some(2),
+                            ("vertex", "nx") => Some(3),
+                            ("vertex", "ny") => Some(4),
+                            ("vertex", "nz") => Some(5),
+                            ("vertex", "u" | "s" | "texture_u" | "texture_s") => Some(6),
+                            ("vertex", "v" | "t" | "texture_v" | "texture_t") => Some(7),
+                            _ => None,
+                        };
+
+                        match slot {
+                            Some(s) => {
+                                values[s] = body.read(*ty)?;
+                                found[s] = true;
+                            },
+                            None => body.skip(*ty)?,
+                        }
+                    },
+                    PlyProperty::List { name, count_ty, item_ty } => {
+                        let count = body.read(*count_ty)? as usize;
+                        body.expect_room(count, body.min_size(*item_ty))?;
+
+                        let is_face_list = element.name == "face" && (name == "vertex_indices" || name == "vertex_index");
+
+                        if is_face_list {
+                            // The count comes from the file, so it isn't trusted to size the polygon up front
+                            let mut polygon: Vec <usize> = Vec::new();
+
+                            for _ in 0..count {
+                                polygon.push(body.read(*item_ty)? as usize);
+                            }
+
+                            polygons.push(polygon);
+                        }
+                        else {
+                            for _ in 0..count {
+                                body.skip(*item_ty)?;
+                            }
+                        }
+                    },
+                }
+            }
+
+            if element.name == "vertex" {
+                positions.push(Point3::new(values[0], values[1], values[2]));
+
+                if found[3] && found[4] && found[5] {
+                    normals.push(Vector3::new(values[3], values[4], values[5]));
+                }
+
+                if found[6] && found[7] {
+                    uvs.push((values[6], values[7]));
+                }
+            }
+        }
+    }
+
+    // PLY attributes are per vertex, so they share the position indices
+    let has_normals = !positions.is_empty() && normals.len() == positions.len();
+    let has_uvs = !positions.is_empty() && uvs.len() == positions.len();
+    let mut faces: Vec <MeshFace> = Vec::new();
+
+    for polygon in polygons {
+        if polygon.len() < 3 {
+            continue;
+        }
+
+        if let Some(&v) = polygon.iter().find(|&&v| v >= positions.len()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("PLY face refers to vertex {} of {}", v, positions.len())));
+        }
+
+        for tri in triangulate(&polygon) {
+            let mut face = MeshFace::new(tri);
+
+            if has_normals {
+                face.normals = Some(tri);
+            }
+
+            if has_uvs {
+                face.uvs = Some(tri);
+            }
+
+            faces.push(face);
+        }
+    }
+
+    if !has_normals {
+        normals.clear();
+    }
+
+    if !has_uvs {
+        uvs.clear();
+    }
+
+    Ok(TriangleMesh::new(positions, normals, uvs, faces))
+}
+
+pub fn read_ply_file<P: AsRef<Path>>(file_path: P) -> io::Result<TriangleMesh> {
+    read_ply(&fs::read(file_path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary_ply_file(format: &str, body: &[u8]) -> Vec <u8> {
+        let header = format!("ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n", format);
+        let mut data = header.into_bytes();
+
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn triangle_body(to_bytes: fn(f32) -> [u8; 4], index_bytes: fn(i32) -> [u8; 4]) -> Vec <u8> {
+        let mut body = Vec::new();
+
+        for v in [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            body.extend_from_slice(&to_bytes(v));
+        }
+
+        body.push(3);
+
+        for i in [0, 1, 2] {
+            body.extend_from_slice(&index_bytes(i));
+        }
+
+        body
+    }
+
+    fn face_vertices(mesh: &TriangleMesh) -> Vec <[usize; 3]> {
+        mesh.faces.iter().map(|f| f.vertices).collect()
+    }
+
+    #[test]
+    fn obj_negative_indices_and_fans() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f -4/-4/-1 -3/-3/-1 -2/-2/-1 -1/-1/-1
+f 1 2 3
+";
+        let mesh = read_obj(obj.as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(face_vertices(&mesh), vec![[0, 1, 2], [0, 2, 3], [0, 1, 2]]);
+        assert_eq!(mesh.faces[1].uvs, Some([0, 2, 3]));
+        assert_eq!(mesh.faces[1].normals, Some([0, 0, 0]));
+        assert_eq!(mesh.faces[2].uvs, None);
+    }
+
+    #[test]
+    fn obj_rejects_bad_faces() {
+        assert!(read_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n".as_bytes()).is_err());
+        assert!(read_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 -4 2\n".as_bytes()).is_err());
+        assert!(read_obj("v 0 0 0\nv 1 0 0\nf 1 2\n".as_bytes()).is_err());
+        assert!(read_obj("v 0 zero 0\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn ascii_ply() {
+        let ply = "\
+ply
+format ascii 1.0
+comment a unit square
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1
+1 0 0 0 0 1
+1 1 0 0 0 1
+0 1 0 0 0 1
+4 0 1 2 3
+";
+        let mesh = read_ply(ply.as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.normals.len(), 4);
+        assert!(mesh.uvs.is_empty());
+        assert_eq!(face_vertices(&mesh), vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.faces[0].normals, Some([0, 1, 2]));
+    }
+
+    #[test]
+    fn binary_ply() {
+        let little = binary_ply_file("binary_little_endian", &triangle_body(f32::to_le_bytes, i32::to_le_bytes));
+        let big = binary_ply_file("binary_big_endian", &triangle_body(f32::to_be_bytes, i32::to_be_bytes));
+
+        for data in [little, big] {
+            let mesh = read_ply(&data).unwrap();
+
+            assert_eq!(mesh.positions.len(), 3);
+            assert!(mesh.positions[1] == Point3::new(1.0, 0.0, 0.0));
+            assert_eq!(face_vertices(&mesh), vec![[0, 1, 2]]);
+        }
+    }
+
+    #[test]
+    fn ply_rejects_truncated_bodies() {
+        let mut body = triangle_body(f32::to_le_bytes, i32::to_le_bytes);
+        body.pop();
+
+        assert!(read_ply(&binary_ply_file("binary_little_endian", &body)).is_err());
+    }
+
+    #[test]
+    fn ply_rejects_out_of_range_faces() {
+        let mut body = triangle_body(f32::to_le_bytes, i32::to_le_bytes);
+        let len = body.len();
+        body[(len - 4)..].copy_from_slice(&3i32.to_le_bytes());
+
+        assert!(read_ply(&binary_ply_file("binary_little_endian", &body)).is_err());
+    }
+
+    // Headers declaring far more data than the file holds must fail quickly, without
+    // allocating or looping for every declared item
+    #[test]
+    fn ply_rejects_huge_counts() {
+        let no_properties = "ply\nformat ascii 1.0\nelement vertex 100000000000\nend_header\n";
+        assert!(read_ply(no_properties.as_bytes()).is_err());
+
+        let huge_vertex_count = "ply\nformat ascii 1.0\nelement vertex 100000000000\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n";
+        assert!(read_ply(huge_vertex_count.as_bytes()).is_err());
+
+        let huge_empty_element = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nelement nothing 18446744073709551615\nend_header\n0 0 0\n";
+        assert!(read_ply(huge_empty_element.as_bytes()).is_ok());
+
+        // Lists other than faces are skipped item by item
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nelement extra 1\nproperty list double uchar data\nend_header\n".to_vec();
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&1e18f64.to_le_bytes());
+        assert_eq!(read_ply(&data).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut body = triangle_body(f32::to_le_bytes, i32::to_le_bytes);
+        body[36] = 255;
+        assert_eq!(read_ply(&binary_ply_file("binary_little_endian", &body)).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn ply_skip_stops_at_the_end() {
+        let mut body = PlyBody { format: PlyFormat::BinaryLittleEndian, data: &[0, 0, 0], pos: 0 };
+
+        assert!(body.skip(PlyScalar::U16).is_ok());
+        assert_eq!(body.skip(PlyScalar::U16).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
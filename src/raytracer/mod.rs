@@ -3,9 +3,33 @@ mod intersect;
 mod color;
 mod render;
 mod file_io;
+mod bvh;
+mod error;
+mod sampler;
+mod filter;
+mod framebuffer;
+mod tonemap;
+mod mesh_io;
+mod path;
+mod integrator;
+mod animation;
+mod builder;
+mod scene_writer;
 
 pub use data::*;
 pub use intersect::*;
 pub use color::*;
 pub use render::*;
-pub use file_io::*;
\ No newline at end of file
+pub use file_io::*;
+pub use bvh::*;
+pub use error::*;
+pub use sampler::*;
+pub use filter::*;
+pub use framebuffer::*;
+pub use tonemap::*;
+pub use mesh_io::*;
+pub use path::*;
+pub use integrator::*;
+pub use animation::*;
+pub use builder::*;
+pub use scene_writer::*;
\ No newline at end of file
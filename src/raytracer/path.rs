@@ -0,0 +1,167 @@
+use std::f64::consts::PI;
+
+use crate::primitives::*;
+use crate::geometry::*;
+use super::{Scene, Rng, IntersectData, MAX_RAY_DIST, refract, intersect_scene_from_view, intersect_scene_from_surface, occluded_from_shape};
+
+// Bounces always traced before Russian roulette may end a path
+const MIN_BOUNCES: usize = 3;
+
+fn max_component(color: &RGBColor) -> f64 {
+    color[0].max(color[1]).max(color[2])
+}
+
+fn reflect(direction: Vector3, surf_norm: Vector3) -> Vector3 {
+    (direction - &(surf_norm * (2.0 * direction.dot(&surf_norm)))).norm()
+}
+
+// Direction around surf_norm with density proportional to the cosine to it
+fn sample_cosine_hemisphere(surf_norm: Vector3, rng: &mut Rng) -> Vector3 {
+    let (t, b) = surf_norm.orthonormal_basis();
+    let (r, phi) = (rng.next_f64().sqrt(), 2.0 * PI * rng.next_f64());
+    let z = (1.0 - r * r).max(0.0).sqrt();
+
+    (t * (r * phi.cos()) + &(b * (r * phi.sin())) + &(surf_norm * z)).norm()
+}
+
+// Light arriving straight from the scene's lights, reflected by the Lambertian part of
+// the material. Area lights are sampled once per call.
+fn sample_direct_light(scene: &Scene, intersect_pt: IntersectData, surf_norm: Vector3, rng: &mut Rng) -> RGBColor {
+    let lights = &scene.lights;
+    let coords = intersect_pt.coords;
+
+    let incoming = lights.lights.iter().fold(RGBColor::new_empty(), |acc, ls| {
+        let (target, color) = match ls {
+            LightType::Directional(d) => (coords + &(d.direction.norm() * MAX_RAY_DIST), d.color),
+            LightType::Point(p) => {
+                let light_dist = (p.position - &coords).len();
+                let attenuation = 1.0 / (lights.attenuation[0] + lights.attenuation[1] * light_dist + lights.attenuation[2] * light_dist * light_dist);
+
+                (p.position, p.color * attenuation)
+            },
+            LightType::Area(a) => {
+                let sample = a.shape.sample(&coords, (rng.next_f64(), rng.next_f64()));
+
+                (sample.position, a.color * sample.weight)
+            },
+        };
+
+        let cos_surf = (target - &coords).norm().dot(&surf_norm);
+
+        if cos_surf <= 0.0 || max_component(&color) <= 0.0 || occluded_from_shape(scene, intersect_pt, target) {
+            acc
+        }
+        else {
+            acc + &(color * cos_surf)
+        }
+    });
+
+    incoming * &(intersect_pt.material.diffuse / PI)
+}
+
+// Radiance along a camera ray, estimated with a single random path. Diffuse reflection
+// is Lambertian and specular reflection an ideal mirror, chosen between at random by
+// their strength. Direct light is gathered at every diffuse surface, so paths that go
+// on to hit an area light after a diffuse bounce don't count it again. Unlike Whitted
+// shading there is no ambient term.
+pub fn trace_path(scene: &Scene, ray: Ray, rng: &mut Rng) -> RGBColor {
+    let mut radiance = RGBColor::new_empty();
+    let mut throughput = RGBColor::new_with_value(1.0);
+    let mut ray = ray;
+    let mut hit = intersect_scene_from_view(ray, scene);
+    let mut count_emitters = true;
+
+    for bounce in 0..=scene.max_recurse_depth {
+        let hit_dist = hit.map(|h| (h.coords - &ray.position).len());
+
+        if let Some((dist, color)) = scene.lights.intersect_emitters(&ray) {
+            if hit_dist.is_none_or(|d| dist < d) {
+                if count_emitters {
+                    radiance = radiance + &(throughput * &color);
+                }
+
+                break;
+            }
+        }
+
+        let (intersect_pt, hit_dist) = match (hit, hit_dist) {
+            (Some(h), Some(d)) => (h, d),
+            _ => break,
+        };
+
+        let material = intersect_pt.material;
+        let direction = ray.direction.norm();
+        let entering = direction.dot(&intersect_pt.geom_normal) < 0.0;
+        let facing_norm = if entering { intersect_pt.normal } else { intersect_pt.normal * -1.0 };
+
+        if !entering && material.is_transmissive() {
+            let absorption = material.absorption;
+
+            throughput = throughput * &RGBColor::new((-absorption[0] * hit_dist).exp(), (-absorption[1] * hit_dist).exp(), (-absorption[2] * hit_dist).exp());
+        }
+
+        radiance = radiance + &(throughput * &material.emission);
+
+        let next_dir = if material.is_transmissive() {
+            let (n1, n2) = if entering { (1.0, material.ior) } else { (material.ior, 1.0) };
+            let cos_i = (-direction.dot(&facing_norm)).clamp(0.0, 1.0);
+
+            count_emitters = true;
+
+            match refract(direction, facing_norm, n1 / n2) {
+                Some(refract_dir) if rng.next_f64() >= material.fresnel.reflectance(cos_i, n1, n2) => {
+                    throughput = throughput * &material.transmission;
+                    refract_dir
+                },
+                _ => reflect(direction, facing_norm),
+            }
+        }
+        else {
+            let diffuse_weight = max_component(&material.diffuse);
+            let specular_weight = max_component(&material.specular);
+
+            if diffuse_weight + specular_weight <= 0.0 {
+                break;
+            }
+
+            if diffuse_weight > 0.0 {
+                radiance = radiance + &(throughput * &sample_direct_light(scene, intersect_pt, facing_norm, rng));
+            }
+
+            let specular_prob = specular_weight / (diffuse_weight + specular_weight);
+
+            if rng.next_f64() < specular_prob {
+                throughput = throughput * &(material.specular / specular_prob);
+                count_emitters = true;
+
+                reflect(direction, facing_norm)
+            }
+            else {
+                // The cosine and 1 / pi of the BRDF cancel against the sampling density
+                throughput = throughput * &(material.diffuse / (1.0 - specular_prob));
+                count_emitters = false;
+
+                sample_cosine_hemisphere(facing_norm, rng)
+            }
+        };
+
+        if bounce >= MIN_BOUNCES {
+            let survival = max_component(&throughput).min(0.95);
+
+            if rng.next_f64() >= survival {
+                break;
+            }
+
+            throughput = throughput / survival;
+        }
+
+        ray = Ray {
+            position: intersect_pt.coords,
+            direction: next_dir,
+            time: intersect_pt.time,
+        };
+        hit = intersect_scene_from_surface(ray, scene, intersect_pt);
+    }
+
+    radiance
+}
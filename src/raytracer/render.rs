@@ -1,77 +1,325 @@
-use image::{DynamicImage, GenericImage};
+use image::{DynamicImage, GenericImage, Rgb, Rgb32FImage};
+use rayon::prelude::*;
 
-use crate::primitives::{Point3, Ray};
-use super::{Scene, intersect_scene_from_view, get_color, get_color_recursive};
+use std::f64::consts::PI;
+
+use crate::primitives::{Point3, Vector3, Ray, Projection};
+use crate::geometry::RGBColor;
+use super::{Scene, Rng, Framebuffer, ToneMapping, Integrator};
+
+// offset is the position relative to the pixel center, in pixels. lens picks the point
+// on the camera's aperture the ray passes through. Returns None where the projection
+// leaves the image black.
+fn make_ray(scene: &Scene, pixel_coords: (usize, usize), offset: (f64, f64), lens: (f64, f64), time: f64) -> Option <Ray> {
+    let camera = &scene.camera;
 
-fn make_ray(scene: &Scene, pixel_coords: (usize, usize)) -> Ray {
     // Create coordinate frame
-    let w = (scene.camera.eye - &scene.camera.center).norm();
-    let u = scene.camera.up.cross(&w).norm();
+    let w = (camera.eye - &camera.center).norm();
+    let u = camera.up.cross(&w).norm();
     let v = w.cross(&u);
+    let eye = Point3 { point: camera.eye.vec };
+
+    // Position on the image relative to its center, in units of half the image height
+    let half_height = 0.5 * (scene.img_height as f64);
+    let image_x = ((((pixel_coords.1 as f64) + 0.5) + offset.0) - (0.5 * (scene.img_width as f64))) / half_height;
+    let image_y = (half_height - ((0.5 + (pixel_coords.0 as f64)) + offset.1)) / half_height;
+
+    match camera.projection {
+        Projection::Perspective => Some(make_perspective_ray(scene, pixel_coords, offset, lens, time, (u, v, w))),
+        Projection::Orthographic { .. } => {
+            let half_view = 0.5 * camera.ortho_height();
+
+            Some(Ray {
+                position: eye + &(u * (image_x * half_view)) + &(v * (image_y * half_view)),
+                direction: w * -1.0,
+                time,
+            })
+        },
+        Projection::Fisheye => {
+            let radius = (image_x * image_x + image_y * image_y).sqrt();
+
+            if radius > 1.0 {
+                return None;
+            }
+
+            let theta = radius * 0.5 * camera.fovy.to_radians();
+            let side = if radius > 0.0 { (u * (image_x / radius) + &(v * (image_y / radius))) * theta.sin() } else { Vector3::new_empty() };
+
+            Some(Ray {
+                position: eye,
+                direction: (side - &(w * theta.cos())).norm(),
+                time,
+            })
+        },
+        Projection::Equirectangular => {
+            let longitude = ((((pixel_coords.1 as f64) + 0.5) + offset.0) / (scene.img_width as f64) - 0.5) * 2.0 * PI;
+            let latitude = image_y * 0.5 * PI;
+
+            Some(Ray {
+                position: eye,
+                direction: (u * (latitude.cos() * longitude.sin()) + &(v * latitude.sin()) - &(w * (latitude.cos() * longitude.cos()))).norm(),
+                time,
+            })
+        },
+    }
+}
+
+fn make_perspective_ray(scene: &Scene, pixel_coords: (usize, usize), offset: (f64, f64), lens: (f64, f64), time: f64, frame: (Vector3, Vector3, Vector3)) -> Ray {
+    let (u, v, w) = frame;
 
     let fov_y_rad = scene.camera.fovy.to_radians();
-    let weight_a = ((0.5 * fov_y_rad).tan() / (0.5 * (scene.img_height as f64))) * (((pixel_coords.1 as f64) + 0.5) - (0.5 * (scene.img_width as f64)));
-    let weight_b = ((0.5 * fov_y_rad).tan() / (0.5 * (scene.img_height as f64))) * ((0.5 * (scene.img_height as f64)) - (0.5 + (pixel_coords.0 as f64)));
+    let weight_a = ((0.5 * fov_y_rad).tan() / (0.5 * (scene.img_height as f64))) * ((((pixel_coords.1 as f64) + 0.5) + offset.0) - (0.5 * (scene.img_width as f64)));
+    let weight_b = ((0.5 * fov_y_rad).tan() / (0.5 * (scene.img_height as f64))) * ((0.5 * (scene.img_height as f64)) - ((0.5 + (pixel_coords.0 as f64)) + offset.1));
 
     let ray_dir = (u * weight_a + &(v * weight_b) - &w).norm();
+    let eye = Point3 { point: scene.camera.eye.vec };
+
+    if scene.camera.aperture <= 0.0 {
+        return Ray {
+            position: eye,
+            direction: ray_dir,
+            time,
+        };
+    }
+
+    // Thin lens: rays through every point of the aperture meet again on the plane of
+    // focus, which lies square to the viewing direction
+    let focus_point = eye + &(ray_dir * (scene.camera.focus_distance() / -ray_dir.dot(&w)));
+    let (lens_x, lens_y) = scene.camera.sample_aperture(lens);
+    let lens_point = eye + &(u * lens_x) + &(v * lens_y);
 
     Ray {
-        position: Point3 { point: scene.camera.eye.vec },
-        direction: ray_dir,
+        position: lens_point,
+        direction: (focus_point - &lens_point).norm(),
+        time,
+    }
+}
+
+pub struct RenderOptions {
+    // Worker threads to render tiles on, 0 picks one per logical CPU
+    pub num_threads: usize,
+    // Width and height of the square tiles the image is split into
+    pub tile_size: usize,
+}
+
+struct Tile {
+    row: usize,
+    col: usize,
+    height: usize,
+    width: usize,
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self {
+            num_threads: 0,
+            tile_size: 16,
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Filtered average of the samples spread over the filter's support around the pixel center
+fn render_pixel(scene: &Scene, integrator: &dyn Integrator, pixel_coords: (usize, usize)) -> RGBColor {
+    let sampling = &scene.sampling;
+    let radius = sampling.filter.radius();
+    // Frames of an animation each get their own noise, while frame 0 keeps the seeds of
+    // a still image
+    let frame_seed = (scene.frame_time as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    let mut rng = Rng::new(((pixel_coords.0 * scene.img_width + pixel_coords.1) as u64) ^ frame_seed);
+
+    let mut color_sum = RGBColor::new_empty();
+    let mut weight_sum = 0.0;
+
+    for (sx, sy) in sampling.pattern.generate(sampling.samples_per_pixel, &mut rng) {
+        let offset = ((sx - 0.5) * 2.0 * radius, (sy - 0.5) * 2.0 * radius);
+        let weight = sampling.filter.weight(offset.0, offset.1);
+
+        if weight == 0.0 {
+            continue;
+        }
+
+        // Only drawn for cameras with a lens, leaving the random numbers of pinhole
+        // renders untouched
+        let lens = if scene.camera.aperture > 0.0 { (rng.next_f64(), rng.next_f64()) } else { (0.5, 0.5) };
+
+        // Likewise only drawn when the shutter stays open for a while
+        let time = scene.frame_time + if scene.camera.has_motion_blur() { scene.camera.shutter_time(rng.next_f64()) } else { scene.camera.shutter_open };
+
+        // Samples the projection leaves out count as black
+        if let Some(ray) = make_ray(scene, pixel_coords, offset, lens, time) {
+            color_sum = color_sum + &(integrator.radiance(scene, ray, &mut rng) * weight);
+        }
+
+        weight_sum += weight;
+    }
+
+    if weight_sum > 0.0 {
+        color_sum / weight_sum
+    }
+    else {
+        RGBColor::new_empty()
+    }
+}
+
+fn render_tile(scene: &Scene, integrator: &dyn Integrator, tile: &Tile) -> Vec <RGBColor> {
+    let mut pixels: Vec <RGBColor> = Vec::with_capacity(tile.width * tile.height);
+
+    for i in tile.row..(tile.row + tile.height) {
+        for j in tile.col..(tile.col + tile.width) {
+            pixels.push(render_pixel(scene, integrator, (i, j)));
+        }
+    }
+
+    pixels
+}
+
+fn make_tiles(scene: &Scene, tile_size: usize) -> Vec <Tile> {
+    let tile_size = tile_size.max(1);
+    let mut tiles: Vec <Tile> = Vec::new();
+
+    for row in (0..scene.img_height).step_by(tile_size) {
+        for col in (0..scene.img_width).step_by(tile_size) {
+            tiles.push(Tile {
+                row,
+                col,
+                height: tile_size.min(scene.img_height - row),
+                width: tile_size.min(scene.img_width - col),
+            });
+        }
     }
+
+    tiles
 }
 
-pub fn render(scene: &Scene) -> Vec <u8> {
-    let mut pixels: Vec <u8> = Vec::new();
-    pixels.resize(scene.img_width * scene.img_height * 3, 0u8);
+// Renders every pixel on the calling thread, in scanline order
+pub fn render_serial(scene: &Scene) -> Framebuffer {
+    let integrator = scene.integrator.integrator();
+    let mut framebuffer = Framebuffer::new(scene.img_width, scene.img_height);
 
     for i in 0..scene.img_height {
         for j in 0..scene.img_width {
-            // Pass through ray in center of (i, j) pixel
-            let ray = make_ray(scene, (i, j));
-            let start_idx = (i * scene.img_width + j) * 3;
-
-            // Intersection test with scene
-            if let Some(id) = intersect_scene_from_view(ray, scene) {
-                // Use get_color_recursive to get reflections
-                //let pix_color = get_color(ray, scene, id, &scene.lights);
-                let pix_color = get_color_recursive(ray, &scene, id, 0);
-                
-                pixels[start_idx + 0] = (255.0 * pix_color[0]) as u8;
-                pixels[start_idx + 1] = (255.0 * pix_color[1]) as u8;
-                pixels[start_idx + 2] = (255.0 * pix_color[2]) as u8;
-            }
-            else {
-                // Color all pixels black
-                pixels[start_idx + 0] = 0u8;
-                pixels[start_idx + 1] = 0u8;
-                pixels[start_idx + 2] = 0u8;
-            }
+            framebuffer.set(j, i, render_pixel(scene, integrator, (i, j)));
         }
     }
 
-    pixels
+    framebuffer
 }
 
-pub fn build_image(image_dim: (usize, usize), pixels: &Vec <u8>) -> DynamicImage {
-    if pixels.len() % 3 != 0 {
-        panic!("Number of pixel values ({}) provided is not divisible by 3!", pixels.len());
+// Renders tiles in parallel with the given integrator, ignoring the one the scene
+// selects. Every pixel is computed independently of the others, so the result does not
+// depend on thread count and tile size.
+pub fn render_with_integrator(scene: &Scene, options: &RenderOptions, integrator: &dyn Integrator) -> Framebuffer {
+    let tiles = make_tiles(scene, options.tile_size);
+    let render_tiles = || -> Vec <Vec <RGBColor>> {
+        tiles.par_iter().map(|t| render_tile(scene, integrator, t)).collect()
+    };
+
+    // Falls back to rayon's global pool when a pool of the requested size can't be
+    // made, which only changes how many threads are used
+    let tile_pixels = match rayon::ThreadPoolBuilder::new().num_threads(options.num_threads).build() {
+        Ok(pool) => pool.install(render_tiles),
+        Err(_) => render_tiles(),
+    };
+
+    let mut framebuffer = Framebuffer::new(scene.img_width, scene.img_height);
+
+    for (tile, tile_pix) in tiles.iter().zip(tile_pixels) {
+        for (r, tile_row) in tile_pix.chunks(tile.width).enumerate() {
+            let start_idx = (tile.row + r) * scene.img_width + tile.col;
+
+            framebuffer.pixels[start_idx..(start_idx + tile_row.len())].copy_from_slice(tile_row);
+        }
     }
 
-    if (pixels.len() / 3) % image_dim.0 != 0 || (pixels.len() / 3) % image_dim.1 != 0 {
-        panic!("Number of pixel values ({}) provided is not divisible by the dimensions!", pixels.len());
+    framebuffer
+}
+
+// Renders tiles in parallel with the scene's integrator. The result is identical to
+// render_serial.
+pub fn render_with_options(scene: &Scene, options: &RenderOptions) -> Framebuffer {
+    render_with_integrator(scene, options, scene.integrator.integrator())
+}
+
+pub fn render(scene: &Scene) -> Framebuffer {
+    render_with_options(scene, &RenderOptions::new())
+}
+
+// 8-bit image for PNG and other low dynamic range formats
+pub fn build_image(framebuffer: &Framebuffer, tone_mapping: &ToneMapping) -> DynamicImage {
+    if framebuffer.pixels.len() != framebuffer.width * framebuffer.height {
+        panic!("Number of pixels ({}) provided does not match the dimensions!", framebuffer.pixels.len());
     }
 
-    let mut image = DynamicImage::new_rgb8(image_dim.0 as u32, image_dim.1 as u32);
+    let mut image = DynamicImage::new_rgb8(framebuffer.width as u32, framebuffer.height as u32);
 
     // Write in column major (height, then width)
-    for y in 0..image_dim.1 {
-        for x in 0..image_dim.0 {
-            let start_idx = (y * image_dim.0 + x) * 3;
+    for y in 0..framebuffer.height {
+        for x in 0..framebuffer.width {
+            let [r, g, b] = tone_mapping.map(framebuffer.get(x, y));
 
-            image.put_pixel(x as u32, y as u32, image::Rgba([pixels[start_idx], pixels[start_idx + 1], pixels[start_idx + 2], 0]));
+            image.put_pixel(x as u32, y as u32, image::Rgba([r, g, b, 0]));
         }
     }
 
     image
-}
\ No newline at end of file
+}
+
+// 32-bit float image keeping the full linear range, for OpenEXR
+pub fn build_hdr_image(framebuffer: &Framebuffer) -> DynamicImage {
+    let mut image = Rgb32FImage::new(framebuffer.width as u32, framebuffer.height as u32);
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let color = framebuffer.get(x as usize, y as usize);
+
+        *pixel = Rgb([color[0] as f32, color[1] as f32, color[2] as f32]);
+    }
+
+    DynamicImage::ImageRgb32F(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::raytracer::{parse_scene_with_mode, ParseMode};
+
+    // Path traced with jittered samples, so every pixel draws plenty of random numbers
+    const SCENE: &str = "\
+size 37 23
+samples 4 jittered
+integrator path
+camera 0 -4 2 0 0 0.5 0 0 1 45
+point 2 -2 4 1 1 1
+rectlight -1 -1 3 2 0 0 0 2 0 2 2 2
+diffuse 0.7 0.7 0.7
+plane 0 0 0 0 0 1
+diffuse 0.8 0.2 0.2
+sphere 0 0 0.75 0.75
+diffuse 0.2 0.8 0.2
+box 1 -0.5 0 1.6 0.5 1
+";
+
+    #[test]
+    fn parallel_render_matches_serial() {
+        let (scene, _) = parse_scene_with_mode(SCENE.as_bytes(), Path::new(""), ParseMode::Strict).unwrap_or_else(|e| panic!("{}", e));
+        let serial = render_serial(&scene);
+
+        assert!(serial.pixels.iter().any(|p| p[0] > 0.0), "scene renders black");
+
+        // 7 and 16 leave partial tiles at the right and bottom edges
+        for (num_threads, tile_size) in [(1, 16), (2, 7), (4, 1), (3, 64), (0, 5)] {
+            let options = RenderOptions { num_threads, tile_size };
+            let parallel = render_with_options(&scene, &options);
+
+            assert_eq!((parallel.width, parallel.height), (serial.width, serial.height));
+            assert!(parallel.pixels == serial.pixels, "{} threads with {}-pixel tiles differ from a serial render", num_threads, tile_size);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::PixelFilter;
+
+// Small PCG32 generator. Seeded per pixel, so that renders are reproducible no matter
+// how pixels are distributed among threads.
+#[derive(Debug, Clone, Copy)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplePattern {
+    // Centers of an evenly spaced grid of strata
+    Grid,
+    // One random position inside each grid stratum
+    Jittered,
+    // Halton sequence in bases 2 and 3, randomly shifted per pixel
+    Halton,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SamplingSettings {
+    pub samples_per_pixel: usize,
+    pub pattern: SamplePattern,
+    pub filter: PixelFilter,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (seed << 1) | 1,
+        };
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(0x853c49e6748fea9b ^ seed);
+        rng.next_u32();
+
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+
+        let xorshifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rot = (old_state >> 59) as u32;
+
+        xorshifted.rotate_right(rot)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u32() as f64) / 4294967296.0
+    }
+}
+
+impl SamplePattern {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "grid" => Some(SamplePattern::Grid),
+            "jittered" => Some(SamplePattern::Jittered),
+            "halton" => Some(SamplePattern::Halton),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplePattern::Grid => "grid",
+            SamplePattern::Jittered => "jittered",
+            SamplePattern::Halton => "halton",
+        }
+    }
+
+    // Sample positions inside the unit square. Grid-based patterns round the count up
+    // to fill a whole grid, so they may return more than count samples.
+    pub fn generate(&self, count: usize, rng: &mut Rng) -> Vec <(f64, f64)> {
+        let count = count.max(1);
+
+        match self {
+            SamplePattern::Grid | SamplePattern::Jittered => {
+                let nx = (count as f64).sqrt().ceil() as usize;
+                let ny = count.div_ceil(nx);
+                let mut samples: Vec <(f64, f64)> = Vec::with_capacity(nx * ny);
+
+                for y in 0..ny {
+                    for x in 0..nx {
+                        let (jx, jy) = if *self == SamplePattern::Jittered {
+                            (rng.next_f64(), rng.next_f64())
+                        }
+                        else {
+                            (0.5, 0.5)
+                        };
+
+                        samples.push((((x as f64) + jx) / (nx as f64), ((y as f64) + jy) / (ny as f64)));
+                    }
+                }
+
+                samples
+            },
+            SamplePattern::Halton => {
+                let (shift_x, shift_y) = (rng.next_f64(), rng.next_f64());
+
+                (0..count).map(|k| {
+                    ((radical_inverse(k + 1, 2) + shift_x).fract(), (radical_inverse(k + 1, 3) + shift_y).fract())
+                }).collect()
+            },
+        }
+    }
+}
+
+fn radical_inverse(mut index: usize, base: usize) -> f64 {
+    let inv_base = 1.0 / (base as f64);
+    let mut inv_base_n = 1.0;
+    let mut ans = 0.0;
+
+    while index > 0 {
+        inv_base_n *= inv_base;
+        ans += ((index % base) as f64) * inv_base_n;
+        index /= base;
+    }
+
+    ans
+}
+
+impl SamplingSettings {
+    // One ray through each pixel center
+    pub fn new() -> Self {
+        Self {
+            samples_per_pixel: 1,
+            pattern: SamplePattern::Grid,
+            filter: PixelFilter::new_box(),
+        }
+    }
+}
+
+impl Default for SamplingSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,752 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use crate::primitives::*;
+use crate::geometry::*;
+use super::{Scene, ToneMapping};
+
+// Writes scenes in the text format read by parse_scene, keeping track of the material,
+// transform and vertices set up so far so that only what changes gets written
+struct SceneWriter<'a> {
+    out: &'a mut dyn Write,
+    // Scene::textures, which materials refer to by index
+    textures: &'a [Texture],
+    material: Material,
+    light_samples: usize,
+    // Transform pushed on top of the base level, the identity when nothing is pushed.
+    // The base level is the scene's, an object's, or a moving shape's keyframes.
+    transform: Matrix4,
+    pushed: bool,
+    vertex_count: usize,
+    vertex_normal_count: usize,
+    vertex_uv_count: usize,
+    // Object definitions instanced so far, with the unique names they are written under
+    objects: Vec <(Arc <ObjectDefinition>, String)>,
+}
+
+fn invalid(problem: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, problem.to_string())
+}
+
+// Arguments are split on whitespace, so names containing it can't be written
+fn word_arg(word: &str) -> io::Result <&str> {
+    if word.is_empty() || word.contains(char::is_whitespace) {
+        Err(invalid(&format!("\"{}\" cannot be written as a single argument", word)))
+    }
+    else {
+        Ok(word)
+    }
+}
+
+impl<'a> SceneWriter<'a> {
+    fn new(out: &'a mut dyn Write, textures: &'a [Texture]) -> Self {
+        Self {
+            out,
+            textures,
+            material: Material::new(),
+            light_samples: 16,
+            transform: Matrix4::new_on_diag(1.0),
+            pushed: false,
+            vertex_count: 0,
+            vertex_normal_count: 0,
+            vertex_uv_count: 0,
+            objects: Vec::new(),
+        }
+    }
+
+    fn line(&mut self, line: &str) -> io::Result <()> {
+        writeln!(self.out, "{}", line)
+    }
+
+    // Command taking only numbers. Numbers are written so that they parse back exactly.
+    fn command(&mut self, name: &str, args: &[f64]) -> io::Result <()> {
+        write!(self.out, "{}", name)?;
+
+        for arg in args {
+            write!(self.out, " {}", arg)?;
+        }
+
+        writeln!(self.out)
+    }
+
+    // Command taking a file path, which must not contain whitespace
+    fn path_command(&mut self, name: &str, path: &str) -> io::Result <()> {
+        let path = word_arg(path)?;
+
+        self.line(&format!("{} {}", name, path))
+    }
+
+    // Sets up the transform and material for the shape command written next
+    fn place(&mut self, transform: &Matrix4, material: &Material) -> io::Result <()> {
+        self.set_transform(transform)?;
+        self.set_material(material)
+    }
+
+    fn set_transform(&mut self, transform: &Matrix4) -> io::Result <()> {
+        if *transform == self.transform {
+            return Ok(());
+        }
+
+        let identity = Matrix4::new_on_diag(1.0);
+
+        if self.pushed {
+            self.line("popTransform")?;
+            self.pushed = false;
+            self.transform = identity;
+        }
+
+        // Multiplying onto the identity leaves the matrix exactly as written
+        if *transform != identity {
+            self.line("pushTransform")?;
+            self.matrix(transform)?;
+            self.pushed = true;
+            self.transform = *transform;
+        }
+
+        Ok(())
+    }
+
+    fn reset_transform(&mut self) -> io::Result <()> {
+        self.set_transform(&Matrix4::new_on_diag(1.0))
+    }
+
+    fn matrix(&mut self, m: &Matrix4) -> io::Result <()> {
+        let mut args = [0.0; 16];
+
+        for (i, arg) in args.iter_mut().enumerate() {
+            *arg = m[[i / 4, i % 4]];
+        }
+
+        self.command("transform", &args)
+    }
+
+    fn set_material(&mut self, material: &Material) -> io::Result <()> {
+        let colors = [
+            ("ambient", material.ambient, self.material.ambient),
+            ("diffuse", material.diffuse, self.material.diffuse),
+            ("specular", material.specular, self.material.specular),
+            ("emission", material.emission, self.material.emission),
+            ("transmission", material.transmission, self.material.transmission),
+            ("absorption", material.absorption, self.material.absorption),
+        ];
+
+        for (name, new, old) in colors {
+            if new != old {
+                self.command(name, &new.vec)?;
+            }
+        }
+
+        if material.shininess != self.material.shininess {
+            self.command("shininess", &[material.shininess])?;
+        }
+
+        if material.ior != self.material.ior {
+            self.command("ior", &[material.ior])?;
+        }
+
+        if material.fresnel != self.material.fresnel {
+            self.line(&format!("fresnel {}", material.fresnel.name()))?;
+        }
+
+        let channels = [MaterialChannel::Ambient, MaterialChannel::Diffuse, MaterialChannel::Specular, MaterialChannel::Emission];
+
+        for channel in channels {
+            let binding = material.textures.get(channel);
+
+            if binding == self.material.textures.get(channel) {
+                continue;
+            }
+
+            // Each texture command adds a texture, so indices aren't kept, only what
+            // each channel is bound to
+            match binding.map(|t| &self.textures[t]) {
+                Some(texture) => {
+                    let source = match &texture.source {
+                        Some(s) => word_arg(s)?,
+                        None => return Err(invalid("textures made in code have no file to refer to")),
+                    };
+
+                    let line = format!("texture {} {} {} {}", channel.name(), source, texture.filter.name(), texture.wrap.name());
+                    self.line(&line)?;
+                },
+                None => self.line(&format!("texture {} none", channel.name()))?,
+            }
+        }
+
+        self.material = *material;
+        Ok(())
+    }
+
+    // Triangle placed with the current transform, through vertices of its own.
+    // Object-space normals or texture coordinates are kept, but not both, since the
+    // format has no triangle taking both.
+    fn triangle(&mut self, vertices: &[Point3; 3], normals: Option <&[Vector3; 3]>, uvs: Option <&[(f64, f64); 3]>) -> io::Result <()> {
+        match (normals, uvs) {
+            (Some(_), Some(_)) => Err(invalid("triangles with both normals and texture coordinates cannot be written")),
+            (Some(normals), None) => {
+                for (v, n) in vertices.iter().zip(normals) {
+                    self.command("vertexnormal", &[v[0], v[1], v[2], n[0], n[1], n[2]])?;
+                }
+
+                let first = self.vertex_normal_count;
+                self.vertex_normal_count += 3;
+
+                self.line(&format!("trinormal {} {} {}", first, first + 1, first + 2))
+            },
+            (None, Some(uvs)) => {
+                for (v, uv) in vertices.iter().zip(uvs) {
+                    self.command("vertextex", &[v[0], v[1], v[2], uv.0, uv.1])?;
+                }
+
+                let first = self.vertex_uv_count;
+                self.vertex_uv_count += 3;
+
+                self.line(&format!("tritex {} {} {}", first, first + 1, first + 2))
+            },
+            (None, None) => {
+                for v in vertices {
+                    self.command("vertex", &[v[0], v[1], v[2]])?;
+                }
+
+                let first = self.vertex_count;
+                self.vertex_count += 3;
+
+                self.line(&format!("tri {} {} {}", first, first + 1, first + 2))
+            },
+        }
+    }
+
+    // Starts a level following the motion, which shapes are then placed relative to
+    // until end_motion. Keyframes after the first are reached by multiplying onto the
+    // previous one, so they come back within rounding of the original.
+    fn begin_motion(&mut self, motion: &MotionTransform) -> io::Result <()> {
+        self.reset_transform()?;
+        self.line("pushTransform")?;
+
+        let mut level: Option <Matrix4> = None;
+
+        for keyframe in motion.keyframes() {
+            let step = match level {
+                Some(l) => keyframe.transform.relative_to(&l),
+                None => keyframe.transform,
+            };
+
+            self.matrix(&step)?;
+            self.command("keyframe", &[keyframe.time])?;
+
+            level = Some(match level {
+                Some(l) => l * &step,
+                None => step,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn end_motion(&mut self) -> io::Result <()> {
+        self.reset_transform()?;
+        self.line("popTransform")
+    }
+
+    // Transforms are kept inside the group, so that its operands' blocks nest in it
+    fn begin_csg(&mut self, op: CsgOp) -> io::Result <()> {
+        self.reset_transform()?;
+        self.line(&format!("beginCsg {}", op.name()))
+    }
+
+    fn end_csg(&mut self) -> io::Result <()> {
+        self.reset_transform()?;
+        self.line("endCsg")
+    }
+
+    // Name an object definition is written under. The first time an object is met its
+    // shapes are written too, which only matters for finding the objects it instances
+    // itself: see write_scene.
+    fn object_name(&mut self, object: &Arc <ObjectDefinition>) -> io::Result <String> {
+        if let Some((_, name)) = self.objects.iter().find(|(o, _)| Arc::ptr_eq(o, object)) {
+            return Ok(name.clone());
+        }
+
+        self.write_shapes(&object.shapes.0)?;
+
+        // Objects redefined under the same name are told apart by a number
+        let base = word_arg(&object.name)?;
+        let mut name = base.to_string();
+        let mut n = 2;
+
+        while self.objects.iter().any(|(_, used)| *used == name) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+
+        self.objects.push((object.clone(), name.clone()));
+        Ok(name)
+    }
+
+    // Commands that recreate the shape. Shapes the format has no commands for, such as
+    // those from other crates, can't be written.
+    fn write_shape(&mut self, shape: &dyn Shape) -> io::Result <()> {
+        let description = match shape.describe() {
+            Some(d) => d,
+            None => return Err(io::Error::new(io::ErrorKind::Unsupported, "shape has no scene file equivalent")),
+        };
+
+        match description {
+            ShapeDescription::Triangle(t) => {
+                self.place(&t.transform, &t.material)?;
+                self.triangle(&t.vertices, t.normals.as_ref(), t.uvs.as_ref())
+            },
+            ShapeDescription::Sphere(s) => {
+                self.place(&s.transform, &s.material)?;
+                self.command("sphere", &[s.center[0], s.center[1], s.center[2], s.radius])
+            },
+            ShapeDescription::Plane(p) => {
+                self.place(&p.transform, &p.material)?;
+                self.command("plane", &[p.point[0], p.point[1], p.point[2], p.normal[0], p.normal[1], p.normal[2]])
+            },
+            ShapeDescription::Box(b) => {
+                self.place(&b.transform, &b.material)?;
+                self.command("box", &[b.min[0], b.min[1], b.min[2], b.max[0], b.max[1], b.max[2]])
+            },
+            ShapeDescription::Cylinder(c) => {
+                self.place(&c.transform, &c.material)?;
+                self.command("cylinder", &[c.center[0], c.center[1], c.center[2], c.radius, c.height])
+            },
+            ShapeDescription::Cone(c) => {
+                self.place(&c.transform, &c.material)?;
+                self.command("cone", &[c.center[0], c.center[1], c.center[2], c.radius, c.height])
+            },
+            ShapeDescription::Disk(d) => {
+                self.place(&d.transform, &d.material)?;
+                self.command("disk", &[d.center[0], d.center[1], d.center[2], d.normal[0], d.normal[1], d.normal[2], d.radius])
+            },
+            ShapeDescription::Torus(t) => {
+                self.place(&t.transform, &t.material)?;
+                self.command("torus", &[t.center[0], t.center[1], t.center[2], t.major_radius, t.minor_radius])
+            },
+            ShapeDescription::Mesh(m) => self.write_mesh(m),
+            ShapeDescription::Csg(csg) => {
+                self.begin_csg(csg.op)?;
+                self.write_shapes(&csg.operands)?;
+                self.end_csg()
+            },
+            ShapeDescription::Instance(instance) => {
+                let name = self.object_name(&instance.object)?;
+
+                self.place(&instance.transform, &instance.material)?;
+                self.line(&format!("instance {} {}", name, if instance.override_material { "override" } else { "keep" }))
+            },
+            ShapeDescription::Moving(moving) => {
+                self.begin_motion(&moving.motion)?;
+                self.write_shape(moving.shape.as_ref())?;
+                self.end_motion()
+            },
+        }
+    }
+
+    fn write_mesh(&mut self, mesh: &Mesh) -> io::Result <()> {
+        self.place(&mesh.transform, &mesh.material)?;
+
+        match &mesh.source {
+            Some(source) => {
+                let is_ply = source.to_ascii_lowercase().ends_with(".ply");
+
+                self.path_command(if is_ply { "ply" } else { "obj" }, source)
+            },
+            // Meshes made in code are written out triangle by triangle
+            None => {
+                let data = &mesh.mesh;
+
+                for face in &data.faces {
+                    let normals = face.normals.map(|n| n.map(|i| data.normals[i]));
+                    let uvs = face.uvs.map(|t| t.map(|i| data.uvs[i]));
+
+                    self.triangle(&face.vertices.map(|i| data.positions[i]), normals.as_ref(), uvs.as_ref())?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    fn write_shapes(&mut self, shapes: &[Box <dyn Shape>]) -> io::Result <()> {
+        for shape in shapes {
+            self.write_shape(shape.as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_objects(&mut self, objects: &[(Arc <ObjectDefinition>, String)]) -> io::Result <()> {
+        for (object, name) in objects {
+            self.line(&format!("beginObject {}", name))?;
+            self.write_shapes(&object.shapes.0)?;
+            self.reset_transform()?;
+            self.line("endObject")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_settings(&mut self, scene: &Scene) -> io::Result <()> {
+        self.line(&format!("size {} {}", scene.img_width, scene.img_height))?;
+        self.line(&format!("maxdepth {}", scene.max_recurse_depth))?;
+
+        if let Some(output) = &scene.output {
+            self.path_command("output", output)?;
+        }
+
+        // Only the radius of the filter can be given in the format, other parameters
+        // are left at their defaults
+        let sampling = &scene.sampling;
+
+        self.line(&format!("samples {} {}", sampling.samples_per_pixel, sampling.pattern.name()))?;
+        self.line(&format!("filter {} {}", sampling.filter.name(), sampling.filter.radius()))?;
+        self.line(&format!("integrator {}", scene.integrator.name()))?;
+
+        let tone_mapping = &scene.tone_mapping;
+
+        if *tone_mapping != ToneMapping::new() {
+            let encoding = if tone_mapping.srgb { "srgb" } else { "linear" };
+
+            self.line(&format!("tonemap {} {} {}", tone_mapping.operator.name(), tone_mapping.exposure, encoding))?;
+        }
+
+        Ok(())
+    }
+
+    // Lens settings are only written when they change from the last camera written
+    fn write_camera_pose(&mut self, camera: &Camera, last: &Camera) -> io::Result <()> {
+        let (eye, center, up) = (&camera.eye, &camera.center, &camera.up);
+
+        self.command("camera", &[eye[0], eye[1], eye[2], center[0], center[1], center[2], up[0], up[1], up[2], camera.fovy])?;
+
+        let lens = |c: &Camera| (c.aperture, c.focus_distance, c.blades, c.blade_rotation);
+
+        if lens(camera) != lens(last) {
+            self.line(&format!("aperture {} {} {} {}", camera.aperture, camera.focus_distance, camera.blades, camera.blade_rotation))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_camera(&mut self, scene: &Scene) -> io::Result <()> {
+        let mut last = Camera::new();
+
+        for keyframe in &scene.camera_keyframes {
+            self.write_camera_pose(&keyframe.camera, &last)?;
+            self.command("cameraKeyframe", &[keyframe.time])?;
+            last = keyframe.camera;
+        }
+
+        let camera = &scene.camera;
+        self.write_camera_pose(camera, &last)?;
+
+        // Projection and shutter are left out where the defaults will do
+        let default = Camera::new();
+
+        if camera.projection != default.projection {
+            match camera.projection {
+                Projection::Orthographic { height } => self.line(&format!("projection orthographic {}", height))?,
+                projection => self.line(&format!("projection {}", projection.name()))?,
+            }
+        }
+
+        if (camera.shutter_open, camera.shutter_close) != (default.shutter_open, default.shutter_close) {
+            self.command("shutter", &[camera.shutter_open, camera.shutter_close])?;
+        }
+
+        if let Some(range) = scene.frame_range {
+            self.line(&format!("frames {} {}", range.first, range.last))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_lights(&mut self, scene: &Scene) -> io::Result <()> {
+        self.command("attenuation", &scene.lights.attenuation)?;
+
+        for light in &scene.lights.lights {
+            match light {
+                LightType::Directional(l) => self.command("directional", &[l.direction[0], l.direction[1], l.direction[2], l.color[0], l.color[1], l.color[2]])?,
+                LightType::Point(l) => self.command("point", &[l.position[0], l.position[1], l.position[2], l.color[0], l.color[1], l.color[2]])?,
+                LightType::Area(l) => {
+                    if l.samples != self.light_samples {
+                        self.line(&format!("lightsamples {}", l.samples))?;
+                        self.light_samples = l.samples;
+                    }
+
+                    let c = &l.color;
+
+                    match &l.shape {
+                        AreaLightShape::Rectangle { corner: p, edge_u: u, edge_v: v } => {
+                            self.command("rectlight", &[p[0], p[1], p[2], u[0], u[1], u[2], v[0], v[1], v[2], c[0], c[1], c[2]])?
+                        },
+                        AreaLightShape::Disk { center: p, normal: n, radius } => {
+                            self.command("disklight", &[p[0], p[1], p[2], n[0], n[1], n[2], *radius, c[0], c[1], c[2]])?
+                        },
+                        AreaLightShape::Sphere { center: p, radius } => {
+                            self.command("spherelight", &[p[0], p[1], p[2], *radius, c[0], c[1], c[2]])?
+                        },
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Writes a scene file that reads back as an equivalent scene: the same settings,
+// camera, lights and shapes, with their materials and transforms. Shapes are written
+// one by one with their own vertices, and meshes and textures refer to the files they
+// were loaded from, as named in the original scene. Fails for shapes and textures made
+// in code that the format can't describe.
+pub fn write_scene<W: Write>(mut out: W, scene: &Scene) -> io::Result <()> {
+    // Objects must be defined before the shapes instancing them, so a first pass that
+    // writes nothing finds every object in the order they depend on each other
+    let mut sink = io::sink();
+    let mut finder = SceneWriter::new(&mut sink, &scene.textures);
+    finder.write_shapes(&scene.shapes.0)?;
+
+    let objects = finder.objects;
+    let mut writer = SceneWriter::new(&mut out, &scene.textures);
+
+    writer.write_settings(scene)?;
+    writer.line("")?;
+    writer.write_camera(scene)?;
+    writer.line("")?;
+    writer.write_lights(scene)?;
+    writer.line("")?;
+
+    writer.objects = objects.clone();
+    writer.write_objects(&objects)?;
+    writer.write_shapes(&scene.shapes.0)?;
+    writer.reset_transform()
+}
+
+pub fn write_scene_file(file_path_str: &str, scene: &Scene) -> io::Result <()> {
+    let mut writer = BufWriter::new(File::create(file_path_str)?);
+
+    write_scene(&mut writer, scene)?;
+    writer.flush()
+}
+
+pub fn scene_to_string(scene: &Scene) -> io::Result <String> {
+    let mut out: Vec <u8> = Vec::new();
+
+    write_scene(&mut out, scene)?;
+    String::from_utf8(out).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::discriminant;
+    use std::path::Path;
+
+    use super::*;
+    use crate::raytracer::{parse_scene_with_mode, ParseMode};
+
+    // Covers the writer's state: objects instancing other objects, transforms pushed
+    // around instances and CSG operands, keyframed shapes and camera, and textures bound
+    // to and cleared from a channel. TEXTURE is replaced with an image file.
+    const SCENE: &str = "\
+size 32 24
+samples 2 jittered
+filter tent 1
+integrator path
+tonemap reinhard 1.5 srgb
+frames 0 3
+camera 0 -6 3 0 0 0.5 0 0 1 40
+cameraKeyframe 0
+camera 2 -5 4 0 0 0.5 0 0 1 35
+aperture 0.1 5 6 0
+cameraKeyframe 3
+projection orthographic 4
+shutter 0 0.5
+point 2 -2 4 1 1 1
+lightsamples 4
+disklight 0 0 4 0 0 -1 1 2 2 2
+diffuse 0.7 0.7 0.7
+texture diffuse TEXTURE nearest mirror
+plane 0 0 0 0 0 1
+texture diffuse none
+beginObject leg
+cylinder 0 0 0 0.1 1
+endObject
+beginObject table
+pushTransform
+translate 0.5 0 0.5
+instance leg keep
+popTransform
+diffuse 0.5 0.3 0.1
+instance leg override
+box -0.6 -0.6 1 0.6 0.6 1.1
+endObject
+pushTransform
+translate 1 1 0
+rotate 0 0 1 30
+instance table keep
+popTransform
+specular 0.3 0.3 0.3
+shininess 20
+beginCsg difference
+sphere 0 0 1 1
+pushTransform
+scale 1 1 2
+cylinder 0 0 0 0.5 2
+popTransform
+endCsg
+pushTransform
+translate -2 0 0
+keyframe 0
+translate 0 0 1
+rotate 0 1 0 45
+keyframe 3
+torus 0 0 0 1 0.25
+pushTransform
+scale 2 2 2
+sphere 0 0 3 0.25
+popTransform
+popTransform
+vertex 0 0 0
+vertex 1 0 0
+vertex 0 1 0
+tri 0 1 2
+";
+
+    fn parse(scene_str: &str) -> Scene {
+        match parse_scene_with_mode(scene_str.as_bytes(), Path::new(""), ParseMode::Strict) {
+            Ok((scene, _)) => scene,
+            Err(e) => panic!("{}\n{}", e, scene_str),
+        }
+    }
+
+    // Keyframes after the first are written relative to the one before, so they only
+    // come back within rounding
+    fn assert_close(a: f64, b: f64) {
+        assert!(a == b || (a - b).abs() <= 1e-9 * (1.0 + a.abs().max(b.abs())), "{} != {}", a, b);
+    }
+
+    fn assert_close_matrix(a: &Matrix4, b: &Matrix4) {
+        for i in 0..16 {
+            assert_close(a[[i / 4, i % 4]], b[[i / 4, i % 4]]);
+        }
+    }
+
+    fn assert_close_bounds(a: &BoundingBox, b: &BoundingBox) {
+        for i in 0..3 {
+            assert_close(a.min[i], b.min[i]);
+            assert_close(a.max[i], b.max[i]);
+        }
+    }
+
+    // Texture indices change, since each texture command adds a texture, so bindings
+    // are compared by what they refer to
+    fn assert_same_material(scene_a: &Scene, a: &Material, scene_b: &Scene, b: &Material) {
+        assert!(a.ambient == b.ambient && a.diffuse == b.diffuse && a.specular == b.specular && a.emission == b.emission);
+        assert!(a.transmission == b.transmission && a.absorption == b.absorption);
+        assert!(a.shininess == b.shininess && a.ior == b.ior && a.fresnel == b.fresnel);
+
+        for channel in [MaterialChannel::Ambient, MaterialChannel::Diffuse, MaterialChannel::Specular, MaterialChannel::Emission] {
+            let describe = |scene: &Scene, m: &Material| m.textures.get(channel).map(|t| {
+                let texture = &scene.textures[t];
+
+                (texture.source.clone(), texture.filter.name(), texture.wrap.name())
+            });
+
+            assert_eq!(describe(scene_a, a), describe(scene_b, b));
+        }
+    }
+
+    fn assert_same_shape(scene_a: &Scene, a: &dyn Shape, scene_b: &Scene, b: &dyn Shape) {
+        let (da, db) = (a.describe().expect("built-in shape"), b.describe().expect("built-in shape"));
+
+        assert_eq!(discriminant(&da), discriminant(&db), "{:?} became {:?}", da, db);
+        assert_close_bounds(&a.bounds(), &b.bounds());
+
+        match (da, db) {
+            (ShapeDescription::Csg(x), ShapeDescription::Csg(y)) => {
+                assert_eq!(x.op, y.op);
+                assert_same_shapes(scene_a, &x.operands, scene_b, &y.operands);
+            },
+            (ShapeDescription::Instance(x), ShapeDescription::Instance(y)) => {
+                assert!(y.object.name.starts_with(&x.object.name));
+                assert_eq!(x.override_material, y.override_material);
+                assert_close_matrix(&x.transform, &y.transform);
+                assert_same_material(scene_a, &x.material, scene_b, &y.material);
+                assert_same_shapes(scene_a, &x.object.shapes.0, scene_b, &y.object.shapes.0);
+            },
+            (ShapeDescription::Moving(x), ShapeDescription::Moving(y)) => {
+                assert_eq!(x.motion.keyframes().len(), y.motion.keyframes().len());
+
+                for (kx, ky) in x.motion.keyframes().iter().zip(y.motion.keyframes()) {
+                    assert_eq!(kx.time, ky.time);
+                    assert_close_matrix(&kx.transform, &ky.transform);
+                }
+
+                assert_same_shape(scene_a, x.shape.as_ref(), scene_b, y.shape.as_ref());
+            },
+            _ => assert_same_material(scene_a, a.material(), scene_b, b.material()),
+        }
+    }
+
+    fn assert_same_shapes(scene_a: &Scene, a: &[Box <dyn Shape>], scene_b: &Scene, b: &[Box <dyn Shape>]) {
+        assert_eq!(a.len(), b.len());
+
+        for (x, y) in a.iter().zip(b) {
+            assert_same_shape(scene_a, x.as_ref(), scene_b, y.as_ref());
+        }
+    }
+
+    fn assert_same_camera(a: &Camera, b: &Camera) {
+        assert!(a.eye == b.eye && a.center == b.center && a.up == b.up && a.fovy == b.fovy);
+        assert!(a.projection == b.projection);
+        assert!(a.aperture == b.aperture && a.focus_distance == b.focus_distance && a.blades == b.blades && a.blade_rotation == b.blade_rotation);
+        assert!(a.shutter_open == b.shutter_open && a.shutter_close == b.shutter_close);
+    }
+
+    #[test]
+    fn scenes_read_back_the_same() {
+        let texture_path = std::env::temp_dir().join(format!("scene_writer_test_{}.png", std::process::id()));
+        let mut image = image::RgbImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        image.save(&texture_path).unwrap();
+
+        let original = parse(&SCENE.replace("TEXTURE", texture_path.to_str().unwrap()));
+        let written = scene_to_string(&original).unwrap();
+        let read_back = parse(&written);
+
+        std::fs::remove_file(&texture_path).unwrap();
+
+        assert_eq!(written.matches("pushTransform").count(), written.matches("popTransform").count());
+
+        assert_eq!((read_back.img_width, read_back.img_height, read_back.max_recurse_depth), (original.img_width, original.img_height, original.max_recurse_depth));
+        assert_eq!(read_back.sampling.samples_per_pixel, original.sampling.samples_per_pixel);
+        assert_eq!(read_back.sampling.pattern, original.sampling.pattern);
+        assert_eq!(read_back.sampling.filter.name(), original.sampling.filter.name());
+        assert_eq!(read_back.sampling.filter.radius(), original.sampling.filter.radius());
+        assert_eq!(read_back.integrator.name(), original.integrator.name());
+        assert!(read_back.tone_mapping == original.tone_mapping);
+        assert_eq!(read_back.frame_range, original.frame_range);
+
+        assert_same_camera(&read_back.camera, &original.camera);
+        assert_eq!(read_back.camera_keyframes.len(), original.camera_keyframes.len());
+
+        for (a, b) in read_back.camera_keyframes.iter().zip(&original.camera_keyframes) {
+            assert_eq!(a.time, b.time);
+            assert_same_camera(&a.camera, &b.camera);
+        }
+
+        assert_eq!(read_back.lights.lights.len(), original.lights.lights.len());
+        assert_same_shapes(&original, &original.shapes.0, &read_back, &read_back.shapes.0);
+    }
+
+    #[test]
+    fn default_camera_settings_are_left_out() {
+        let written = scene_to_string(&parse("size 4 4\ncamera 0 0 5 0 0 0 0 1 0 45\n")).unwrap();
+
+        assert!(!written.contains("projection") && !written.contains("shutter"), "{}", written);
+    }
+}
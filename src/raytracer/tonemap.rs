@@ -0,0 +1,90 @@
+use crate::geometry::RGBColor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    // Cut off everything above 1
+    Clamp,
+    // c / (1 + c) per channel
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+}
+
+// How linear radiance is turned into 8-bit values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // Multiplier applied before the operator
+    pub exposure: f64,
+    // Encode with the sRGB transfer curve instead of storing linear values
+    pub srgb: bool,
+}
+
+impl ToneMapOperator {
+    pub fn from_name(name: &str) -> Option <Self> {
+        match name {
+            "clamp" => Some(ToneMapOperator::Clamp),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "aces" => Some(ToneMapOperator::AcesFilmic),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::AcesFilmic => "aces",
+        }
+    }
+
+    fn map_channel(&self, x: f64) -> f64 {
+        match self {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::AcesFilmic => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        }
+    }
+}
+
+impl ToneMapping {
+    // Plain clamping of linear values, which is what the renderer has always written
+    pub fn new() -> Self {
+        Self {
+            operator: ToneMapOperator::Clamp,
+            exposure: 1.0,
+            srgb: false,
+        }
+    }
+
+    pub fn map(&self, color: RGBColor) -> [u8; 3] {
+        let mut ans = [0u8; 3];
+
+        for (i, out) in ans.iter_mut().enumerate() {
+            let mut v = self.operator.map_channel((color[i] * self.exposure).max(0.0)).min(1.0);
+
+            if self.srgb {
+                v = linear_to_srgb(v);
+            }
+
+            *out = (255.0 * v) as u8;
+        }
+
+        ans
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        12.92 * v
+    }
+    else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}